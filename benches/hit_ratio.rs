@@ -0,0 +1,98 @@
+// compares the hit ratio of the replacement policies on the same
+// synthetic page trace, run with `cargo bench --bench hit_ratio`
+//
+// the trace mixes skewed accesses to a small hot set with cold
+// random accesses and the occasional sequential scan, roughly what
+// an index with a hot root and range queries looks like
+
+use std::{collections::HashSet, time::Instant};
+
+use forklift::{buffer::replacer::ReplacementPolicy, storage::page::PageID};
+
+const FRAMES: usize = 64;
+const PAGES: u32 = 1024;
+const ACCESSES: usize = 200_000;
+
+// xorshift64, keeps the trace identical between runs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn build_trace() -> Vec<PageID> {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let mut trace = Vec::with_capacity(ACCESSES);
+
+    while trace.len() < ACCESSES {
+        match rng.next() % 100 {
+            // hot set, skewed towards the lowest page ids
+            0..=69 => {
+                let a = rng.next() % 48;
+                let b = rng.next() % 48;
+                trace.push(1 + a.min(b) as PageID);
+            }
+            // cold random page
+            70..=98 => trace.push(1 + (rng.next() % PAGES as u64) as PageID),
+            // sequential scan over twice the pool size
+            _ => {
+                let start = rng.next() % (PAGES as u64 - 2 * FRAMES as u64);
+                for page_id in start..start + 2 * FRAMES as u64 {
+                    trace.push(1 + page_id as PageID);
+                }
+            }
+        }
+    }
+
+    trace.truncate(ACCESSES);
+    trace
+}
+
+fn simulate(policy: ReplacementPolicy, trace: &[PageID]) -> f64 {
+    let mut replacer = policy.build(FRAMES);
+    let mut resident: HashSet<PageID> = HashSet::with_capacity(FRAMES);
+    let mut hits = 0;
+
+    for page_id in trace {
+        if resident.contains(page_id) {
+            hits += 1;
+        } else if resident.len() == FRAMES {
+            let victim = replacer.evict().expect("no pages are pinned");
+            resident.remove(&victim);
+        }
+
+        resident.insert(*page_id);
+        replacer.record_access(*page_id);
+        replacer.set_evictable(*page_id, true);
+    }
+
+    hits as f64 / trace.len() as f64
+}
+
+fn main() {
+    let trace = build_trace();
+    println!("{ACCESSES} accesses over {PAGES} pages with {FRAMES} frames");
+
+    for policy in [
+        ReplacementPolicy::Lru,
+        ReplacementPolicy::LruK(2),
+        ReplacementPolicy::Clock,
+        ReplacementPolicy::ClockPro,
+        ReplacementPolicy::Arc,
+        ReplacementPolicy::TwoQ,
+    ] {
+        let start = Instant::now();
+        let hit_ratio = simulate(policy, &trace);
+        println!(
+            "{:<12} hit ratio {:>6.2}%  in {:?}",
+            format!("{policy:?}"),
+            hit_ratio * 100.0,
+            start.elapsed()
+        );
+    }
+}
@@ -0,0 +1,261 @@
+// memory the frames of a pool live in
+//
+// a pool allocates room for all of its frames at once, when
+// it is created, as blocks laid out like pages on disk: the
+// page header followed by the content, aligned to the page
+// size. a page is read from disk straight into a slot, which the
+// frame caching it keeps until it is dropped, so caching a page
+// does not go through the allocator and the memory used stays the
+// same however many pages come and go
+//
+// a frame evicted while someone still holds it keeps its slot
+// until it is dropped, once every slot is taken no page can be
+// read until one is given back
+
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use crate::storage::page::{FRAME_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE};
+
+// a page as stored on disk, header first
+#[repr(C, align(4096))]
+struct Block {
+    header: [u8; PAGE_HEADER_SIZE as usize],
+    content: [u8; FRAME_SIZE as usize],
+}
+
+const _: () = assert!(std::mem::size_of::<Block>() == PAGE_SIZE as usize);
+
+impl Block {
+    fn new() -> Block {
+        Block {
+            header: [0; PAGE_HEADER_SIZE as usize],
+            content: [0; FRAME_SIZE as usize],
+        }
+    }
+}
+
+struct Slot(UnsafeCell<Block>);
+
+// SAFETY: the content of a slot is only reached through the single
+// `FrameBuf` holding it, shared access goes through `&FrameBuf` and
+// mutable access through `&mut FrameBuf`
+unsafe impl Sync for Slot {}
+
+struct Slots {
+    slots: Box<[Slot]>,
+    free: Mutex<Vec<usize>>,
+    // woken whenever a slot is given back
+    released: Arc<Notify>,
+}
+
+/// Block aligned memory for a fixed number of frames, cloning the
+/// arena shares it
+#[derive(Clone)]
+pub struct FrameArena {
+    inner: Arc<Slots>,
+}
+
+impl FrameArena {
+    /// Arena for `frames` frames, `released` is notified whenever a
+    /// slot is given back
+    pub fn new(frames: usize, released: Arc<Notify>) -> FrameArena {
+        let slots: Box<[Slot]> = (0..frames)
+            .map(|_| Slot(UnsafeCell::new(Block::new())))
+            .collect();
+        // slots are handed out from the back, lowest first
+        let free = (0..frames).rev().collect();
+
+        FrameArena {
+            inner: Arc::new(Slots {
+                slots,
+                free: Mutex::new(free),
+                released,
+            }),
+        }
+    }
+
+    /// Number of frames the arena holds
+    pub fn capacity(&self) -> usize {
+        self.inner.slots.len()
+    }
+
+    /// Number of slots not held by a frame
+    pub fn available(&self) -> usize {
+        self.inner.free.lock().len()
+    }
+
+    /// A zeroed buffer for a frame taken from the arena, `None`
+    /// when every slot is held
+    pub fn alloc(&self) -> Option<FrameBuf> {
+        let Some(slot) = self.inner.free.lock().pop() else {
+            println!("[DEBUG][ARENA] no free slot");
+            return None;
+        };
+
+        let mut buf = FrameBuf {
+            memory: Memory::Slot(Arc::clone(&self.inner), slot),
+        };
+        buf.page_mut().fill(0);
+        Some(buf)
+    }
+}
+
+impl fmt::Debug for FrameArena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameArena")
+            .field("capacity", &self.capacity())
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+enum Memory {
+    Slot(Arc<Slots>, usize),
+    Heap(Box<Block>),
+}
+
+/// Content of a frame, a slot of a `FrameArena` or a buffer of its
+/// own. Dereferences to the frame bytes, a slot is returned to its
+/// arena when the buffer is dropped
+pub struct FrameBuf {
+    memory: Memory,
+}
+
+impl FrameBuf {
+    /// Whether the buffer lives in an arena
+    pub fn in_arena(&self) -> bool {
+        matches!(self.memory, Memory::Slot(..))
+    }
+
+    /// The page as stored on disk, the header bytes followed by the
+    /// frame content
+    pub fn page(&self) -> &[u8; PAGE_SIZE as usize] {
+        // SAFETY: `Block` is `repr(C)` without padding, see the size
+        // assertion above
+        unsafe { &*(self.block() as *const Block).cast() }
+    }
+
+    pub fn page_mut(&mut self) -> &mut [u8; PAGE_SIZE as usize] {
+        // SAFETY: see `page`
+        unsafe { &mut *(self.block_mut() as *mut Block).cast() }
+    }
+
+    fn block(&self) -> &Block {
+        match &self.memory {
+            // SAFETY: the slot was taken off the free list for this
+            // buffer, nothing else reaches it until the buffer is dropped
+            Memory::Slot(slots, slot) => unsafe { &*slots.slots[*slot].0.get() },
+            Memory::Heap(block) => block,
+        }
+    }
+
+    fn block_mut(&mut self) -> &mut Block {
+        match &mut self.memory {
+            // SAFETY: see `block`, `&mut self` makes the access exclusive
+            Memory::Slot(slots, slot) => unsafe { &mut *slots.slots[*slot].0.get() },
+            Memory::Heap(block) => block,
+        }
+    }
+}
+
+impl Default for FrameBuf {
+    fn default() -> FrameBuf {
+        FrameBuf {
+            memory: Memory::Heap(Box::new(Block::new())),
+        }
+    }
+}
+
+impl Deref for FrameBuf {
+    type Target = [u8; FRAME_SIZE as usize];
+
+    fn deref(&self) -> &[u8; FRAME_SIZE as usize] {
+        &self.block().content
+    }
+}
+
+impl DerefMut for FrameBuf {
+    fn deref_mut(&mut self) -> &mut [u8; FRAME_SIZE as usize] {
+        &mut self.block_mut().content
+    }
+}
+
+impl Drop for FrameBuf {
+    fn drop(&mut self) {
+        if let Memory::Slot(slots, slot) = &self.memory {
+            slots.free.lock().push(*slot);
+            slots.released.notify_waiters();
+        }
+    }
+}
+
+impl fmt::Debug for FrameBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.memory {
+            Memory::Slot(_, slot) => write!(f, "FrameBuf(slot {slot})"),
+            Memory::Heap(_) => write!(f, "FrameBuf(heap)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, thread};
+
+    use crate::storage::page::{PAGE_HEADER_SIZE, PAGE_SIZE};
+
+    use super::FrameArena;
+
+    #[test]
+    fn test_slots_are_reused() {
+        let arena = FrameArena::new(2, Arc::default());
+        let mut first = arena.alloc().unwrap();
+        let second = arena.alloc().unwrap();
+        assert!(first.in_arena() && second.in_arena());
+        assert_eq!(arena.available(), 0);
+        // the content follows the page header of a block aligned slot
+        let block = first.page().as_ptr() as usize;
+        assert_eq!(block % PAGE_SIZE as usize, 0);
+        assert_eq!(first.as_ptr() as usize, block + PAGE_HEADER_SIZE as usize);
+
+        // a full arena hands out nothing
+        assert!(arena.alloc().is_none());
+
+        first.page_mut().fill(7);
+        let address = first.as_ptr();
+        drop(first);
+        assert_eq!(arena.available(), 1);
+
+        // the slot comes back zeroed, header included
+        let reused = arena.alloc().unwrap();
+        assert_eq!(reused.as_ptr(), address);
+        assert!(reused.page().iter().all(|byte| *byte == 0));
+        drop(second);
+        drop(reused);
+        assert_eq!(arena.available(), 2);
+    }
+
+    #[test]
+    fn test_buffers_move_across_threads() {
+        let arena = FrameArena::new(4, Arc::default());
+        let buffers: Vec<_> = (0..4).map(|_| arena.alloc().unwrap()).collect();
+
+        thread::scope(|scope| {
+            for (value, mut buf) in buffers.into_iter().enumerate() {
+                scope.spawn(move || {
+                    buf.fill(value as u8);
+                    assert!(buf.iter().all(|byte| *byte == value as u8));
+                });
+            }
+        });
+        assert_eq!(arena.available(), 4);
+    }
+}
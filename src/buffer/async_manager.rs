@@ -0,0 +1,380 @@
+// async front end of the buffer pool for callers running
+// on a tokio runtime
+//
+// the DiskManager lock is only ever held for short, non
+// blocking sections. reads of pages that are not cached
+// and write backs of evicted frames are awaited on their
+// DiskScheduler futures with the lock released, the log
+// is forced ahead of a write back on a blocking thread,
+// and a fetch that finds every frame pinned waits until
+// a frame is unpinned instead of failing
+//
+// closing waits for the write backs of every cached frame,
+// it runs on a blocking thread, on drop as well
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::{
+    error::{Error, Result},
+    storage::page::PageID,
+    wal::{LogManager, Lsn},
+};
+
+use super::{
+    cache::{PageTable, PinnedFrame},
+    guard::{ReadPageGuard, WritePageGuard},
+    replacer::ReplacementPolicy,
+    scheduler::{DiskManager, FlushAttempt, InstallOutcome, PinOutcome, FLUSH_LATCH_TIMEOUT},
+};
+
+pub struct AsyncBufferPoolManager {
+    disk_manager: Arc<Mutex<DiskManager>>,
+    page_table: PageTable,
+    wal: Arc<LogManager>,
+}
+
+impl AsyncBufferPoolManager {
+    pub async fn new(
+        max_frames: usize,
+        db_file: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<AsyncBufferPoolManager> {
+        let db_file = db_file.to_owned();
+        let disk_manager =
+            tokio::task::spawn_blocking(move || DiskManager::new(max_frames, &db_file, policy))
+                .await??;
+
+        Ok(AsyncBufferPoolManager {
+            page_table: disk_manager.page_table(),
+            wal: disk_manager.wal(),
+            disk_manager: Arc::new(Mutex::new(disk_manager)),
+        })
+    }
+
+    pub async fn open(
+        max_frames: usize,
+        db_file: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<AsyncBufferPoolManager> {
+        let db_file = db_file.to_owned();
+        let disk_manager =
+            tokio::task::spawn_blocking(move || DiskManager::open(max_frames, &db_file, policy))
+                .await??;
+
+        Ok(AsyncBufferPoolManager {
+            page_table: disk_manager.page_table(),
+            wal: disk_manager.wal(),
+            disk_manager: Arc::new(Mutex::new(disk_manager)),
+        })
+    }
+
+    /// Async counterpart of `BufferPoolManager::fetch_page_read`
+    pub async fn fetch_page_read(&self, page_id: PageID) -> Result<ReadPageGuard> {
+        let (frame, pins) = self.pin_page(page_id).await?;
+
+        if let Some(latch) = frame.try_read_arc() {
+            return Ok(ReadPageGuard::from_latch(page_id, latch, pins));
+        }
+        // a writer holds the frame, wait for it off the executor
+        let guard =
+            tokio::task::spawn_blocking(move || ReadPageGuard::new(page_id, frame, pins)).await?;
+        Ok(guard)
+    }
+
+    /// Async counterpart of `BufferPoolManager::fetch_page_write`
+    pub async fn fetch_page_write(&self, page_id: PageID) -> Result<WritePageGuard> {
+        self.disk_manager.lock().unwrap().check_writable()?;
+        let (frame, pins) = self.pin_page(page_id).await?;
+        let (wal, scheduler) = {
+            let disk_manager = self.disk_manager.lock().unwrap();
+            (disk_manager.wal(), disk_manager.scheduler())
+        };
+
+        if let Some(latch) = frame.try_write_arc() {
+            return Ok(WritePageGuard::from_latch(
+                page_id, latch, pins, wal, scheduler,
+            ));
+        }
+        let guard = tokio::task::spawn_blocking(move || {
+            WritePageGuard::new(page_id, frame, pins, wal, scheduler)
+        })
+        .await?;
+        Ok(guard)
+    }
+
+    pub async fn new_page(&self) -> Result<PageID> {
+        let disk_manager = Arc::clone(&self.disk_manager);
+        tokio::task::spawn_blocking(move || disk_manager.lock().unwrap().new_page()).await?
+    }
+
+    /// Writes a cached page back if it is dirty, a writer holding
+    /// the frame is waited for with the disk manager released, up to
+    /// `FLUSH_LATCH_TIMEOUT`. Fails with `Error::PagePinned` once it
+    /// runs out, e.g. while the caller holds a guard on the page
+    pub async fn flush_page(&self, page_id: PageID) -> Result<()> {
+        loop {
+            let attempt = self.disk_manager.lock().unwrap().schedule_flush(page_id)?;
+            match attempt {
+                FlushAttempt::Scheduled(flush) => return flush.wait_async().await,
+                FlushAttempt::NotCached => return Err(Error::PageNotCached(page_id)),
+                FlushAttempt::Clean => return Ok(()),
+                FlushAttempt::Latched(frame) => {
+                    let released = tokio::task::spawn_blocking(move || {
+                        frame.try_write_for(FLUSH_LATCH_TIMEOUT).is_some()
+                    })
+                    .await?;
+                    if !released {
+                        return Err(Error::PagePinned(page_id));
+                    }
+                }
+                FlushAttempt::LogBehind(lsn) => self.force_log(lsn).await?,
+            }
+        }
+    }
+
+    pub async fn delete_page(&self, page_id: PageID) -> Result<()> {
+        let disk_manager = Arc::clone(&self.disk_manager);
+        tokio::task::spawn_blocking(move || disk_manager.lock().unwrap().delete_page(page_id))
+            .await?
+    }
+
+    /// Writes back cached pages and persists the page directory
+    pub async fn close(&self) -> Result<()> {
+        let disk_manager = Arc::clone(&self.disk_manager);
+        tokio::task::spawn_blocking(move || disk_manager.lock().unwrap().close()).await?
+    }
+
+    // makes the log durable up to `lsn` on a blocking thread, a
+    // frame with that page LSN may be written back afterwards
+    async fn force_log(&self, lsn: Lsn) -> Result<()> {
+        let wal = Arc::clone(&self.wal);
+        tokio::task::spawn_blocking(move || wal.flush(lsn)).await?
+    }
+
+    async fn pin_page(&self, page_id: PageID) -> Result<PinnedFrame> {
+        if let Some(pinned) = self.page_table.pin_frame(page_id) {
+            return Ok(pinned);
+        }
+        let released = self.disk_manager.lock().unwrap().frame_released();
+
+        loop {
+            // registered before trying, so an unpin racing with a
+            // failed attempt still wakes us up
+            let notified = released.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let outcome = self.disk_manager.lock().unwrap().try_pin_page(page_id);
+            let mut read = match outcome {
+                Ok(PinOutcome::Pinned(pinned)) => return Ok(pinned),
+                Ok(PinOutcome::Pending(read)) => read,
+                // every frame buffer holds a page, or one being read
+                Err(Error::NoFreeFrames) => {
+                    notified.await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let mut content = (&mut read.future).await?;
+            loop {
+                let installed = self
+                    .disk_manager
+                    .lock()
+                    .unwrap()
+                    .install_page(&read, content);
+
+                match installed {
+                    Ok(InstallOutcome::Installed(pinned)) => return Ok(pinned),
+                    // the page was written back while we read it
+                    Ok(InstallOutcome::Stale) => break,
+                    // the frame in the way stays cached until its write
+                    // back completed, the page is installed again after
+                    Ok(InstallOutcome::WriteBack(flush, page)) => {
+                        flush.wait_async().await?;
+                        content = page;
+                    }
+                    Ok(InstallOutcome::LogBehind(lsn, page)) => {
+                        self.force_log(lsn).await?;
+                        content = page;
+                    }
+                    Err(Error::NoFreeFrames) => {
+                        notified.await;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for AsyncBufferPoolManager {
+    // the disk manager would close itself on drop, waiting for its
+    // write backs on the executor thread. it is closed on a thread
+    // outside the runtime instead
+    fn drop(&mut self) {
+        let closed = thread::scope(|scope| {
+            scope
+                .spawn(|| self.disk_manager.lock().unwrap().close())
+                .join()
+        });
+        if let Ok(Err(e)) = closed {
+            println!("[DEBUG][AsyncBufferPoolManager] failed to close cleanly: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, sync::Arc, time::Duration};
+
+    use crate::{buffer::replacer::ReplacementPolicy, error::Error, wal::INVALID_TXN};
+
+    use super::AsyncBufferPoolManager;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_async_fetch_and_evict() {
+        const FILE_PATH: &str = "/tmp/test_async_fetch_and_evict.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let bpm = AsyncBufferPoolManager::new(2, FILE_PATH, ReplacementPolicy::Lru)
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            bpm.new_page().await.unwrap();
+        }
+
+        let mut guard = bpm.fetch_page_write(1).await.unwrap();
+        guard.content[0] = 7;
+        drop(guard);
+
+        // fetching 2 and 3 evicts page 1, which is read back from disk
+        drop(bpm.fetch_page_read(2).await.unwrap());
+        drop(bpm.fetch_page_read(3).await.unwrap());
+        let guard = bpm.fetch_page_read(1).await.unwrap();
+        assert_eq!(guard.content[0], 7);
+        drop(guard);
+
+        assert!(bpm.fetch_page_read(9).await.is_err());
+
+        bpm.close().await.unwrap();
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_async_waits_for_free_frame() {
+        const FILE_PATH: &str = "/tmp/test_async_waits_for_free_frame.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let bpm = Arc::new(
+            AsyncBufferPoolManager::new(2, FILE_PATH, ReplacementPolicy::Lru)
+                .await
+                .unwrap(),
+        );
+        for _ in 0..3 {
+            bpm.new_page().await.unwrap();
+        }
+
+        let first = bpm.fetch_page_read(1).await.unwrap();
+        let second = bpm.fetch_page_read(2).await.unwrap();
+
+        // every frame is pinned, the fetch waits instead of failing
+        let waiting = {
+            let bpm = Arc::clone(&bpm);
+            tokio::spawn(async move { bpm.fetch_page_read(3).await.map(|guard| guard.page_id()) })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        assert_eq!(waiting.await.unwrap().unwrap(), 3);
+        drop(second);
+
+        bpm.close().await.unwrap();
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_async_forces_log_before_write_back() {
+        const FILE_PATH: &str = "/tmp/test_async_forces_log_before_write_back.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let bpm = AsyncBufferPoolManager::new(2, FILE_PATH, ReplacementPolicy::Lru)
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            bpm.new_page().await.unwrap();
+        }
+
+        let mut guard = bpm.fetch_page_write(1).await.unwrap();
+        let lsn = guard.write_logged(INVALID_TXN, 0, &[1]).unwrap();
+        drop(guard);
+        assert!(!bpm.wal.is_durable(lsn));
+        bpm.flush_page(1).await.unwrap();
+        assert!(bpm.wal.is_durable(lsn));
+
+        // evicting a frame whose log records are not durable yet
+        let mut guard = bpm.fetch_page_write(2).await.unwrap();
+        let lsn = guard.write_logged(INVALID_TXN, 0, &[2]).unwrap();
+        drop(guard);
+        drop(bpm.fetch_page_read(1).await.unwrap());
+        drop(bpm.fetch_page_read(3).await.unwrap());
+        assert!(bpm.wal.is_durable(lsn));
+        assert_eq!(bpm.fetch_page_read(2).await.unwrap().content[0], 2);
+
+        bpm.close().await.unwrap();
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_async_flush_fails_while_caller_holds_latch() {
+        const FILE_PATH: &str = "/tmp/test_async_flush_fails_while_caller_holds_latch.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let bpm = AsyncBufferPoolManager::new(2, FILE_PATH, ReplacementPolicy::Lru)
+            .await
+            .unwrap();
+        let page_id = bpm.new_page().await.unwrap();
+        bpm.fetch_page_write(page_id).await.unwrap().content[0] = 1;
+
+        let guard = bpm.fetch_page_read(page_id).await.unwrap();
+        assert!(matches!(
+            bpm.flush_page(page_id).await,
+            Err(Error::PagePinned(id)) if id == page_id
+        ));
+        drop(guard);
+        bpm.flush_page(page_id).await.unwrap();
+
+        bpm.close().await.unwrap();
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_async_drop_writes_back() {
+        const FILE_PATH: &str = "/tmp/test_async_drop_writes_back.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let bpm = AsyncBufferPoolManager::new(2, FILE_PATH, ReplacementPolicy::Lru)
+            .await
+            .unwrap();
+        let page_id = bpm.new_page().await.unwrap();
+        let mut guard = bpm.fetch_page_write(page_id).await.unwrap();
+        guard.content[0] = 5;
+        drop(guard);
+
+        // dropped on the only runtime thread with a dirty frame
+        drop(bpm);
+
+        let bpm = AsyncBufferPoolManager::open(2, FILE_PATH, ReplacementPolicy::Lru)
+            .await
+            .unwrap();
+        assert_eq!(bpm.fetch_page_read(page_id).await.unwrap().content[0], 5);
+
+        bpm.close().await.unwrap();
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+}
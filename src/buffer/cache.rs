@@ -1,210 +1,587 @@
 use std::{
     collections::HashMap,
-    fmt::Debug,
-    ptr,
-    sync::{Arc, RwLock},
+    fmt::{self, Debug},
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
-use crate::storage::page::{Frame, PageID, FRAME_SIZE};
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::Notify;
 
-#[derive(Debug, Clone)]
-pub struct CacheEntry {
+use crate::{
+    storage::page::{Frame, PageID},
+    wal::Lsn,
+};
+
+use super::{
+    arena::{FrameArena, FrameBuf},
+    replacer::{lru::LruReplacer, Replacer},
+};
+
+// slots of the frame arena beyond the cached frames, a page is
+// read into one before room is made for it in the cache
+const READ_SLOTS: usize = 4;
+
+// number of guards currently holding a frame, shared
+// between the cache entry and the guards so that
+// unpinning does not need the cache
+pub type PinCount = Arc<FramePins>;
+
+#[derive(Debug)]
+pub struct FramePins {
     page_id: PageID,
+    count: AtomicUsize,
+    // shared by every frame of a cache, woken whenever
+    // a frame becomes unpinned
+    released: Arc<Notify>,
+    // pages pinned or unpinned since the replacer was last told,
+    // shared by every frame of a cache. `queued` keeps a page
+    // from being listed twice
+    changed: Arc<Mutex<Vec<PageID>>>,
+    queued: AtomicBool,
+}
+
+impl FramePins {
+    fn new(page_id: PageID, released: Arc<Notify>, changed: Arc<Mutex<Vec<PageID>>>) -> FramePins {
+        FramePins {
+            page_id,
+            count: AtomicUsize::new(0),
+            released,
+            changed,
+            queued: AtomicBool::new(false),
+        }
+    }
+
+    pub fn pin(&self) {
+        if self.count.fetch_add(1, Ordering::SeqCst) == 0 {
+            self.queue_change();
+        }
+    }
+
+    pub fn unpin(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.queue_change();
+            self.released.notify_waiters();
+        }
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        self.count.load(Ordering::SeqCst) > 0
+    }
+
+    fn queue_change(&self) {
+        if !self.queued.swap(true, Ordering::SeqCst) {
+            self.changed.lock().push(self.page_id);
+        }
+    }
+}
+
+// a frame together with the pin count taken on it
+pub type PinnedFrame = (Arc<RwLock<Frame>>, PinCount);
+
+#[derive(Debug)]
+pub struct CacheEntry {
     frame: Arc<RwLock<Frame>>,
-    prev: *mut CacheEntry,
-    next: *mut CacheEntry,
+    pins: PinCount,
+    // reference bit of the page, handed to replacers that
+    // use them so an access does not need the replacer
+    referenced: Arc<AtomicBool>,
 }
 
 impl CacheEntry {
-    fn new(frame: Frame) -> CacheEntry {
-        CacheEntry {
-            page_id: frame.page_id,
-            frame: Arc::new(RwLock::new(frame)),
-            prev: ptr::null_mut(),
-            next: ptr::null_mut(),
+    fn is_pinned(&self) -> bool {
+        self.pins.is_pinned()
+    }
+
+    fn pin(&self) -> PinnedFrame {
+        self.pins.pin();
+        (Arc::clone(&self.frame), Arc::clone(&self.pins))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    NoFreeFrames,
+    FramePinned(PageID),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::NoFreeFrames => write!(f, "every frame in the cache is pinned"),
+            Self::FramePinned(page_id) => write!(f, "page {page_id} is pinned"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// frames held in memory live in a slab of slots, the map
+// points at their slot. slots of evicted frames are reused
+#[derive(Debug, Default)]
+struct Frames {
+    slots: Vec<Option<CacheEntry>>,
+    free_slots: Vec<usize>,
+    map: HashMap<PageID, usize>,
+    // frames can be pinned through a `PageTable`, the replacer
+    // reads accesses from the reference bits
+    shared_pins: bool,
+}
+
+impl Frames {
+    fn entry(&self, page_id: PageID) -> Option<&CacheEntry> {
+        let slot = *self.map.get(&page_id)?;
+        self.slots[slot].as_ref()
+    }
+
+    fn insert(&mut self, page_id: PageID, entry: CacheEntry) {
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(entry);
+                slot
+            }
+            None => {
+                self.slots.push(Some(entry));
+                self.slots.len() - 1
+            }
+        };
+        self.map.insert(page_id, slot);
+    }
+
+    // empties the slot of a frame, it is reused by the next frame added
+    fn take_entry(&mut self, page_id: PageID) -> Option<CacheEntry> {
+        let slot = self.map.remove(&page_id)?;
+        self.free_slots.push(slot);
+        self.slots[slot].take()
+    }
+}
+
+/// Shared view of the frames of a cache, pins cached pages without
+/// locking the cache, or the disk manager holding it
+#[derive(Debug, Clone)]
+pub struct PageTable {
+    frames: Arc<RwLock<Frames>>,
+}
+
+impl PageTable {
+    /// Pins a cached page and sets its reference bit. `None` when
+    /// the page is not cached or the replacer of the cache has to
+    /// see every access, the page is pinned through the cache then
+    pub fn pin_frame(&self, page_id: PageID) -> Option<PinnedFrame> {
+        let frames = self.frames.read();
+        if !frames.shared_pins {
+            return None;
         }
+
+        // pinned under the shared lock, a frame is only removed
+        // under the exclusive one after checking its pin count
+        let entry = frames.entry(page_id)?;
+        entry.referenced.store(true, Ordering::Relaxed);
+        Some(entry.pin())
     }
 }
 
 #[derive(Debug)]
-pub struct Cache {
-    // Using a Heap to implement an
-    // LRU-K cache
+pub struct Cache<R: Replacer = Box<dyn Replacer>> {
     pub max_frames: usize,
-    // Hashmap and DLL based LRU
-
-    // NOTE: a lookup on this hashmap with the key, the value
-    // is a Box pointers, which is later casted to a raw pointer
-    // to get its position in the linked list to move it around
-    // in the cache
-    map: HashMap<PageID, *mut CacheEntry>,
-    head: *mut CacheEntry,
-    tail: *mut CacheEntry,
+
+    // shared with the page tables handed out, the order in which
+    // frames are evicted is left to the replacer
+    frames: Arc<RwLock<Frames>>,
+    // memory for the content of every frame, allocated up front
+    arena: FrameArena,
+    replacer: R,
+    released: Arc<Notify>,
+    changed: Arc<Mutex<Vec<PageID>>>,
 }
 
 impl Cache {
     pub fn new(max_frames: usize) -> Cache {
+        Cache::with_replacer(max_frames, Box::new(LruReplacer::new()))
+    }
+}
+
+impl<R: Replacer> Cache<R> {
+    pub fn with_replacer(max_frames: usize, replacer: R) -> Cache<R> {
+        let frames = Frames {
+            slots: Vec::with_capacity(max_frames),
+            shared_pins: replacer.uses_reference_bits(),
+            ..Frames::default()
+        };
+
+        let released = Arc::new(Notify::new());
         Cache {
             max_frames,
-            map: HashMap::new(),
-            head: ptr::null_mut(),
-            tail: ptr::null_mut(),
+            frames: Arc::new(RwLock::new(frames)),
+            arena: FrameArena::new(max_frames + READ_SLOTS, Arc::clone(&released)),
+            replacer,
+            released,
+            changed: Arc::default(),
         }
     }
 
-    pub fn lookup_frame(&mut self, page_id: PageID) -> Option<Arc<RwLock<Frame>>> {
-        let entry = self.map.get(&page_id);
+    /// Notified whenever a frame of this cache is unpinned or a
+    /// frame buffer is given back, lets async callers wait for a
+    /// frame instead of failing with `Error::NoFreeFrames`
+    pub fn frame_released(&self) -> Arc<Notify> {
+        Arc::clone(&self.released)
+    }
 
-        if entry.is_none() {
-            println!("[DEBUG][CACHE] cache miss");
-            return None;
+    /// Handle pinning cached pages without the cache, see `PageTable`
+    pub fn page_table(&self) -> PageTable {
+        PageTable {
+            frames: Arc::clone(&self.frames),
         }
+    }
 
-        println!("[DEBUG][CACHE] cache hit");
-
-        let entry_ptr = *(entry.unwrap());
+    /// Stops page tables from pinning frames, every page is pinned
+    /// through the cache from now on
+    pub fn disable_page_table(&mut self) {
+        self.frames.write().shared_pins = false;
+    }
 
-        unsafe {
-            let prev = (*entry_ptr).prev;
-            let next = (*entry_ptr).next;
+    pub fn contains(&self, page_id: PageID) -> bool {
+        self.frames.read().map.contains_key(&page_id)
+    }
 
-            if !prev.is_null() && !next.is_null() {
-                (*prev).next = next;
-                (*next).prev = prev;
+    pub fn lookup_frame(&mut self, page_id: PageID) -> Option<Arc<RwLock<Frame>>> {
+        let frames = self.frames.read();
+        let Some(entry) = frames.entry(page_id) else {
+            println!("[DEBUG][CACHE] cache miss");
+            return None;
+        };
 
-                (*self.head).prev = entry_ptr;
-                (*entry_ptr).next = self.head;
-                self.head = entry_ptr;
-                (*self.head).prev = ptr::null_mut();
-            } else if !prev.is_null() && next.is_null() {
-                (*prev).next = ptr::null_mut();
-                self.tail = prev;
+        println!("[DEBUG][CACHE] cache hit");
 
-                (*self.head).prev = entry_ptr;
-                (*entry_ptr).next = self.head;
-                self.head = entry_ptr;
-                (*self.head).prev = ptr::null_mut();
-            }
+        let frame = Arc::clone(&entry.frame);
+        if frames.shared_pins {
+            entry.referenced.store(true, Ordering::Relaxed);
+        } else {
+            self.replacer.record_access(page_id);
         }
 
-        test_iter(self.head);
+        Some(frame)
+    }
 
-        unsafe {
-            return Some(Arc::clone(&(*entry_ptr).frame));
-        }
+    /// Looks up a frame without recording an access, used by flushes
+    /// so that writing a page back does not make it look recently used
+    pub fn peek_frame(&self, page_id: PageID) -> Option<Arc<RwLock<Frame>>> {
+        let frames = self.frames.read();
+        frames.entry(page_id).map(|entry| Arc::clone(&entry.frame))
     }
 
-    pub fn evict_frame(&mut self, page_id: PageID) {
-        let entry = self.map.get(&page_id);
+    /// Looks up a frame like `Cache::lookup_frame` and pins it, the
+    /// frame will not be evicted until the returned pin count is
+    /// decremented again
+    pub fn pin_frame(&mut self, page_id: PageID) -> Option<PinnedFrame> {
+        self.lookup_frame(page_id)?;
+        self.frames.read().entry(page_id).map(CacheEntry::pin)
+    }
+
+    /// Pins a frame without recording an access, keeps a frame
+    /// cached while it is written back
+    pub fn pin_unrecorded(&self, page_id: PageID) -> Option<PinnedFrame> {
+        self.frames.read().entry(page_id).map(CacheEntry::pin)
+    }
 
-        if entry.is_none() {
+    pub fn is_pinned(&self, page_id: PageID) -> bool {
+        let frames = self.frames.read();
+        frames.entry(page_id).is_some_and(CacheEntry::is_pinned)
+    }
+
+    /// Removes a frame from the cache without writing it back,
+    /// fails if the frame is pinned
+    pub fn evict_frame(&mut self, page_id: PageID) -> Result<Option<Arc<RwLock<Frame>>>, Error> {
+        let mut frames = self.frames.write();
+        let Some(entry) = frames.entry(page_id) else {
             println!("[DEBUG][CACHE] cache miss");
-            return;
+            return Ok(None);
+        };
+
+        if entry.is_pinned() {
+            return Err(Error::FramePinned(page_id));
         }
 
-        let entry_ptr = *(entry.unwrap());
+        let entry = frames.take_entry(page_id).unwrap();
+        self.replacer.remove(page_id);
 
-        unsafe {
-            let prev = (*entry_ptr).prev;
-            let next = (*entry_ptr).next;
+        Ok(Some(entry.frame))
+    }
 
-            if !prev.is_null() && !next.is_null() {
-                (*prev).next = next;
-                (*next).prev = prev;
+    /// Returns a reference to every frame currently held in the
+    /// cache, without recording an access
+    pub fn frames(&self) -> Vec<Arc<RwLock<Frame>>> {
+        self.frames
+            .read()
+            .slots
+            .iter()
+            .flatten()
+            .map(|entry| Arc::clone(&entry.frame))
+            .collect()
+    }
 
-                (*self.head).prev = entry_ptr;
-                (*entry_ptr).next = self.head;
-                self.head = entry_ptr;
-                (*self.head).prev = ptr::null_mut();
-            } else if !prev.is_null() && next.is_null() {
-                (*prev).next = ptr::null_mut();
-                self.tail = prev;
+    /// Up to `count` unpinned pages the replacer would evict next
+    pub fn eviction_candidates(&mut self, count: usize) -> Vec<PageID> {
+        self.refresh_evictable();
+        self.replacer.candidates(count)
+    }
+
+    /// Page ids of every frame currently held in the cache
+    pub fn page_ids(&self) -> Vec<PageID> {
+        self.frames.read().map.keys().copied().collect()
+    }
 
-                (*self.head).prev = entry_ptr;
-                (*entry_ptr).next = self.head;
-                self.head = entry_ptr;
-                (*self.head).prev = ptr::null_mut();
+    /// Picks the frame to evict before a frame can be added to a
+    /// full cache, `None` while there is room. The victim stays
+    /// cached, so it can be written back first, until it is dropped
+    /// with `Cache::remove_victim` or handed back to the replacer with
+    /// `Cache::keep_victim`. Pinned frames are never picked, when every
+    /// frame is pinned `Error::NoFreeFrames` is returned
+    ///
+    /// with `clean_only` frames that would have to be written back
+    /// are skipped as well
+    pub fn select_victim(&mut self, clean_only: bool) -> Result<Option<PageID>, Error> {
+        let cached = self.frames.read().map.len();
+        if cached < self.max_frames {
+            return Ok(None);
+        }
+        self.refresh_evictable();
+
+        let victim = if clean_only {
+            let frames = self.frames.read();
+            let victim = self
+                .replacer
+                .candidates(cached)
+                .into_iter()
+                .find(|page_id| {
+                    // latched frames are being written, they are not clean yet
+                    frames.entry(*page_id).is_some_and(|entry| {
+                        entry.frame.try_read().is_some_and(|frame| !frame.dirty)
+                    })
+                });
+            if let Some(victim) = victim {
+                self.replacer.remove(victim);
+            }
+            victim
+        } else {
+            self.replacer.evict()
+        };
+
+        match victim {
+            Some(victim) => Ok(Some(victim)),
+            None => {
+                println!("[DEBUG][CACHE] every frame is pinned");
+                Err(Error::NoFreeFrames)
             }
         }
     }
 
+    /// Drops a victim picked by `Cache::select_victim` without
+    /// writing it back. A victim pinned through a `PageTable` since
+    /// it was picked stays cached and `None` is returned
+    pub fn remove_victim(&mut self, page_id: PageID) -> Option<Arc<RwLock<Frame>>> {
+        let mut frames = self.frames.write();
+        if frames.entry(page_id)?.is_pinned() {
+            drop(frames);
+            self.keep_victim(page_id);
+            return None;
+        }
+
+        let entry = frames.take_entry(page_id)?;
+        println!("[DEBUG][CACHE] Evicting frame of page {page_id}");
+        Some(entry.frame)
+    }
+
+    /// Keeps a victim picked by `Cache::select_victim` cached, e.g.
+    /// because writing it back failed, it can be picked again later.
+    /// The replacer puts it back where it was, this is no access
+    pub fn keep_victim(&mut self, page_id: PageID) {
+        let frames = self.frames.read();
+        if let Some(entry) = frames.entry(page_id) {
+            self.replacer.restore(page_id, &entry.referenced);
+            self.replacer.set_evictable(page_id, !entry.is_pinned());
+        }
+    }
+
+    /// A buffer from the frame arena a page is read into before it
+    /// is added with `Cache::put_frame`. Fails with `Error::NoFreeFrames`
+    /// while every slot of the arena is held
+    pub fn frame_buffer(&self) -> Result<FrameBuf, Error> {
+        self.arena.alloc().ok_or(Error::NoFreeFrames)
+    }
+
     /// Adds a frame with specified page_id, memory offset,
-    /// content to the cahce
-    /// The return value is an Option<Arc<..>> reference to the
-    /// frame being evicted
+    /// page LSN and content to the cahce, the content stays in
+    /// the buffer it was read into. Room has to be made
+    /// with `Cache::select_victim` first, when the cache is full
+    /// the frame is not added and `Error::NoFreeFrames` is returned
     pub fn put_frame(
         &mut self,
         page_id: PageID,
         offset: usize,
-        content: Box<[u8; FRAME_SIZE as usize]>,
-    ) -> Option<Arc<RwLock<Frame>>> {
-        let mut evict: Option<Arc<RwLock<Frame>>> = None;
-
-        if self.map.len() + 1 > self.max_frames {
-            unsafe {
-                let entry = self.tail;
-
-                if !entry.is_null() && ((*entry).next == (*entry).prev) {
-                    // one element
-                    self.head = ptr::null_mut();
-                    self.tail = ptr::null_mut();
-                } else if !entry.is_null() {
-                    self.tail = (*entry).prev;
-                    (*self.tail).next = ptr::null_mut();
-                }
-
-                let key = (*(entry)).page_id;
-                let mut frame = Arc::clone(&(*self.map.remove(&key).unwrap()).frame);
-                frame = Arc::clone(&frame);
-                evict = Some(frame.clone());
-                println!("[DEBUG][CACHE] Evicting frame {}", frame.read().unwrap());
-            }
+        page_lsn: Lsn,
+        content: FrameBuf,
+    ) -> Result<(), Error> {
+        let mut frames = self.frames.write();
+        if frames.map.len() >= self.max_frames {
+            return Err(Error::NoFreeFrames);
         }
 
-        let entry = Box::new(CacheEntry::new(Frame::new(page_id, offset, content)));
+        let mut frame = Frame::new(page_id, offset, content);
+        frame.page_lsn = page_lsn;
+        let pins = FramePins::new(
+            page_id,
+            Arc::clone(&self.released),
+            Arc::clone(&self.changed),
+        );
+        let entry = CacheEntry {
+            frame: Arc::new(RwLock::new(frame)),
+            pins: Arc::new(pins),
+            referenced: Arc::default(),
+        };
 
-        let entry_ptr = Box::into_raw(entry);
+        self.replacer.track(page_id, &entry.referenced);
+        self.replacer.set_evictable(page_id, true);
+        frames.insert(page_id, entry);
 
-        unsafe {
-            if self.tail.is_null() {
-                self.tail = entry_ptr;
-                self.head = entry_ptr;
-            } else {
-                (*entry_ptr).next = self.head;
-                (*self.head).prev = entry_ptr;
-                self.head = entry_ptr;
-            }
+        Ok(())
+    }
 
-            self.map.insert(page_id, entry_ptr);
-            // dbg!(&self
-            //     .map
-            //     .values()
-            //     .map(|val| (val.frame.page_id, val.prev, val.next))
-            //     .collect::<Vec<_>>());
+    // guards unpin without going through the cache, so the
+    // replacer learns which frames became pinned or unpinned
+    // right before it has to pick a victim
+    fn refresh_evictable(&mut self) {
+        let changed = mem::take(&mut *self.changed.lock());
+        let frames = self.frames.read();
+        for page_id in changed {
+            if let Some(entry) = frames.entry(page_id) {
+                // cleared first, a change racing with the refresh
+                // queues the page again
+                entry.pins.queued.store(false, Ordering::SeqCst);
+                self.replacer.set_evictable(page_id, !entry.is_pinned());
+            }
         }
+    }
+}
 
-        test_iter(self.head);
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, thread};
 
-        return evict;
+    use crate::buffer::replacer::ReplacementPolicy;
+
+    use super::{Cache, Error, READ_SLOTS};
+
+    fn put(cache: &mut Cache, page_id: u32) {
+        let mut content = cache.frame_buffer().unwrap();
+        content.fill(page_id as u8);
+        cache
+            .put_frame(page_id, page_id as usize, 0, content)
+            .unwrap();
     }
-}
 
-fn test_iter(head: *mut CacheEntry) {
-    unsafe {
-        let start = head;
-        let mut ptr = start;
+    #[test]
+    fn test_insert_lookup_evict() {
+        let mut cache = Cache::new(3);
+        for page_id in 1..=3 {
+            put(&mut cache, page_id);
+        }
+        assert!(matches!(
+            cache.put_frame(4, 4, 0, cache.frame_buffer().unwrap()),
+            Err(Error::NoFreeFrames)
+        ));
+
+        // 1 becomes the most recently used, 3 is pinned
+        assert_eq!(cache.lookup_frame(1).unwrap().read().content[0], 1);
+        let (_, pins) = cache.pin_frame(3).unwrap();
+        assert_eq!(cache.select_victim(false).unwrap(), Some(2));
+        assert!(cache.remove_victim(2).is_some());
+        assert!(cache.lookup_frame(2).is_none());
+
+        // pages come and go, evicted slots are reused
+        for page_id in 4..64 {
+            put(&mut cache, page_id);
+            let victim = cache.select_victim(false).unwrap().unwrap();
+            assert_ne!(victim, 3);
+            cache.remove_victim(victim).unwrap();
+        }
+        assert_eq!(cache.frames.read().slots.len(), 3);
+        assert_eq!(cache.page_ids().len(), 2);
+        // every frame lives in the arena, evicted frames gave theirs back
+        assert!(cache
+            .frames()
+            .iter()
+            .all(|frame| frame.read().content.in_arena()));
+        assert_eq!(cache.arena.available(), 1 + READ_SLOTS);
+
+        // pages being read hold buffers as well, once every one
+        // is taken no further page can be read
+        let reading: Vec<_> = (0..=READ_SLOTS)
+            .map(|_| cache.frame_buffer().unwrap())
+            .collect();
+        assert!(matches!(cache.frame_buffer(), Err(Error::NoFreeFrames)));
+        drop(reading);
+
+        assert!(matches!(cache.evict_frame(3), Err(Error::FramePinned(3))));
+        pins.unpin();
+        assert!(cache.evict_frame(3).unwrap().is_some());
+        assert_eq!(cache.frames().len(), 1);
+    }
 
-        loop {
-            if ptr.is_null() {
-                break;
-            }
-            println!(
-                "\tnode: {}, prev {:?}, next {:?}",
-                (*ptr).page_id,
-                (*ptr).prev,
-                (*ptr).next
-            );
-            ptr = (*ptr).next;
+    #[test]
+    fn test_page_table_pins_with_reference_bits() {
+        // lru has to see every access, pages are pinned through the cache
+        let mut cache = Cache::new(2);
+        put(&mut cache, 1);
+        assert!(cache.page_table().pin_frame(1).is_none());
+
+        let mut cache = Cache::with_replacer(2, ReplacementPolicy::Clock.build(2));
+        put(&mut cache, 1);
+        put(&mut cache, 2);
+        let table = cache.page_table();
+
+        // 1 is pinned through the table after it was picked
+        assert_eq!(cache.select_victim(false).unwrap(), Some(1));
+        let (_, pins) = table.pin_frame(1).unwrap();
+        assert!(cache.remove_victim(1).is_none());
+        assert_eq!(cache.select_victim(false).unwrap(), Some(2));
+        assert!(cache.remove_victim(2).is_some());
+        assert!(table.pin_frame(2).is_none());
+
+        pins.unpin();
+        cache.disable_page_table();
+        assert!(table.pin_frame(1).is_none());
+        assert!(cache.pin_frame(1).is_some());
+
+        // clock-pro reads the bits as well
+        let mut cache = Cache::with_replacer(2, ReplacementPolicy::ClockPro.build(2));
+        put(&mut cache, 1);
+        let (_, pins) = cache.page_table().pin_frame(1).unwrap();
+        pins.unpin();
+    }
+
+    #[test]
+    fn test_cache_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Cache>();
+
+        let mut cache = Cache::new(4);
+        for page_id in 1..=4 {
+            put(&mut cache, page_id);
         }
+        let cache = Arc::new(cache);
+
+        thread::scope(|scope| {
+            for page_id in 1..=4 {
+                let cache = Arc::clone(&cache);
+                scope.spawn(move || {
+                    let frame = cache.peek_frame(page_id).unwrap();
+                    assert_eq!(frame.read().content[0], page_id as u8);
+                });
+            }
+        });
     }
 }
@@ -0,0 +1,349 @@
+// RAII guards handed out by the BufferPoolManager
+//
+// a guard pins its frame for as long as it lives, so the
+// frame can not be evicted from under it, and holds the
+// frame latch (shared for reads, exclusive for writes).
+// dropping the guard releases the latch and unpins the frame
+//
+// changes made through `WritePageGuard::write_logged` are
+// appended to the write ahead log first, changes made through
+// `DerefMut` are not logged
+//
+// `PageRef` and `PageMut` wrap a guard and dereference straight
+// to the bytes of the page, or a part of them, borrowed from the
+// frame in the pool without copying
+//
+// guards also read, write and seek within the page like a file,
+// from a cursor starting at the beginning of the page. flushing a
+// write guard writes a copy of the page through the disk scheduler
+// of the pool, without locking the pool
+
+use std::{
+    io::{self, BufRead, Cursor, Read, Seek, SeekFrom, Write},
+    ops::{Deref, DerefMut, Range},
+    sync::Arc,
+};
+
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, RawRwLock, RwLock};
+
+use crate::{
+    error::Result,
+    storage::page::{Frame, PageID},
+    wal::{record::LogBody, LogManager, Lsn, TxnId},
+};
+
+use super::{
+    cache::PinCount,
+    scheduler::{page_range, DiskScheduler, PageImage},
+};
+
+pub struct ReadPageGuard {
+    page_id: PageID,
+    pins: PinCount,
+    latch: ArcRwLockReadGuard<RawRwLock, Frame>,
+    // the frame can not be changed under a shared latch,
+    // the guard keeps its own position
+    cursor: u64,
+}
+
+impl ReadPageGuard {
+    pub fn new(page_id: PageID, frame: Arc<RwLock<Frame>>, pins: PinCount) -> ReadPageGuard {
+        ReadPageGuard {
+            page_id,
+            pins,
+            latch: frame.read_arc(),
+            cursor: 0,
+        }
+    }
+
+    /// Wraps a latch that was already taken on the pinned frame
+    pub fn from_latch(
+        page_id: PageID,
+        latch: ArcRwLockReadGuard<RawRwLock, Frame>,
+        pins: PinCount,
+    ) -> ReadPageGuard {
+        ReadPageGuard {
+            page_id,
+            pins,
+            latch,
+            cursor: 0,
+        }
+    }
+
+    pub fn page_id(&self) -> PageID {
+        self.page_id
+    }
+
+    /// `len` bytes of the page content starting at `offset`
+    pub fn into_bytes(self, offset: usize, len: usize) -> Result<PageRef> {
+        let range = page_range(self.page_id, offset, len)?;
+        Ok(PageRef { guard: self, range })
+    }
+}
+
+impl Deref for ReadPageGuard {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        &self.latch
+    }
+}
+
+impl Drop for ReadPageGuard {
+    fn drop(&mut self) {
+        self.pins.unpin();
+    }
+}
+
+impl ReadPageGuard {
+    fn content_cursor(&self) -> Cursor<&[u8]> {
+        let mut cursor = Cursor::new(&self.latch.content[..]);
+        cursor.set_position(self.cursor);
+        cursor
+    }
+}
+
+impl Read for ReadPageGuard {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut cursor = self.content_cursor();
+        let read = cursor.read(buf)?;
+        self.cursor = cursor.position();
+        Ok(read)
+    }
+}
+
+impl BufRead for ReadPageGuard {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let start = (self.cursor as usize).min(self.latch.content.len());
+        Ok(&self.latch.content[start..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.cursor += amt as u64;
+    }
+}
+
+impl Seek for ReadPageGuard {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.cursor = self.content_cursor().seek(pos)?;
+        Ok(self.cursor)
+    }
+}
+
+pub struct WritePageGuard {
+    page_id: PageID,
+    pins: PinCount,
+    latch: ArcRwLockWriteGuard<RawRwLock, Frame>,
+    wal: Arc<LogManager>,
+    // scheduler of the pool holding the frame, flushes go through it
+    // without locking the pool
+    scheduler: Arc<DiskScheduler>,
+}
+
+impl WritePageGuard {
+    pub fn new(
+        page_id: PageID,
+        frame: Arc<RwLock<Frame>>,
+        pins: PinCount,
+        wal: Arc<LogManager>,
+        scheduler: Arc<DiskScheduler>,
+    ) -> WritePageGuard {
+        WritePageGuard::from_latch(page_id, frame.write_arc(), pins, wal, scheduler)
+    }
+
+    /// Wraps a latch that was already taken on the pinned frame
+    pub fn from_latch(
+        page_id: PageID,
+        mut latch: ArcRwLockWriteGuard<RawRwLock, Frame>,
+        pins: PinCount,
+        wal: Arc<LogManager>,
+        scheduler: Arc<DiskScheduler>,
+    ) -> WritePageGuard {
+        // the cursor of the frame is left over from the last guard
+        latch.cursor = 0;
+        WritePageGuard {
+            page_id,
+            pins,
+            latch,
+            wal,
+            scheduler,
+        }
+    }
+
+    pub fn page_id(&self) -> PageID {
+        self.page_id
+    }
+
+    /// Overwrites the content at `offset` with `bytes`, logging the
+    /// change as part of `txn_id` first. Returns the LSN of the log
+    /// record, which becomes the page LSN of the frame. Changes made
+    /// with `INVALID_TXN` are redone after a crash but never undone
+    pub fn write_logged(&mut self, txn_id: TxnId, offset: usize, bytes: &[u8]) -> Result<Lsn> {
+        let range = page_range(self.page_id, offset, bytes.len())?;
+        let before = &self.latch.content[range.clone()];
+
+        let lsn = self.wal.append(
+            txn_id,
+            LogBody::PageWrite {
+                page_id: self.page_id,
+                offset,
+                before: before.to_vec(),
+                after: bytes.to_vec(),
+            },
+        )?;
+
+        let frame = &mut **self;
+        frame.content[range].copy_from_slice(bytes);
+        frame.page_lsn = lsn;
+
+        Ok(lsn)
+    }
+
+    /// Writes the page back if it is dirty, keeping the latch. The
+    /// page is copied and handed to the disk scheduler of the pool,
+    /// the pool itself is not locked while the latch is held. Returns
+    /// whether the page was written. Blocks until the write completes,
+    /// see `DiskFuture::wait`
+    pub fn flush_page(&mut self) -> Result<bool> {
+        if !self.latch.dirty {
+            return Ok(false);
+        }
+
+        // the log has to be durable up to the last change first
+        self.wal.flush(self.latch.page_lsn)?;
+        let page = PageImage::snapshot(&self.latch);
+        self.scheduler
+            .write(self.page_id, self.latch.offset, page)
+            .wait()?;
+
+        // the latch is held throughout, nothing modified the frame
+        self.latch.dirty = false;
+        Ok(true)
+    }
+
+    /// `len` bytes of the page content starting at `offset`, the
+    /// frame is marked dirty once they are modified
+    pub fn into_bytes(self, offset: usize, len: usize) -> Result<PageMut> {
+        let range = page_range(self.page_id, offset, len)?;
+        Ok(PageMut { guard: self, range })
+    }
+}
+
+impl Deref for WritePageGuard {
+    type Target = Frame;
+
+    fn deref(&self) -> &Frame {
+        &self.latch
+    }
+}
+
+impl DerefMut for WritePageGuard {
+    // any mutable access is treated as a modification
+    fn deref_mut(&mut self) -> &mut Frame {
+        self.latch.mark_dirty();
+        &mut self.latch
+    }
+}
+
+impl Drop for WritePageGuard {
+    fn drop(&mut self) {
+        self.pins.unpin();
+    }
+}
+
+// reads and seeks go to the frame directly, they do not mark it dirty
+impl Read for WritePageGuard {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.latch.read(buf)
+    }
+}
+
+impl BufRead for WritePageGuard {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.latch.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.latch.consume(amt)
+    }
+}
+
+impl Seek for WritePageGuard {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.latch.seek(pos)
+    }
+}
+
+impl Write for WritePageGuard {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.latch.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_page()?;
+        Ok(())
+    }
+}
+
+/// Bytes of a page borrowed from its frame, the page stays pinned
+/// and latched for reads while they are borrowed
+pub struct PageRef {
+    guard: ReadPageGuard,
+    range: Range<usize>,
+}
+
+impl PageRef {
+    pub fn page_id(&self) -> PageID {
+        self.guard.page_id
+    }
+}
+
+impl From<ReadPageGuard> for PageRef {
+    fn from(guard: ReadPageGuard) -> PageRef {
+        let range = 0..guard.content.len();
+        PageRef { guard, range }
+    }
+}
+
+impl Deref for PageRef {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.guard.content[self.range.clone()]
+    }
+}
+
+/// Bytes of a page borrowed mutably from its frame, the page stays
+/// pinned and latched for writes while they are borrowed
+pub struct PageMut {
+    guard: WritePageGuard,
+    range: Range<usize>,
+}
+
+impl PageMut {
+    pub fn page_id(&self) -> PageID {
+        self.guard.page_id
+    }
+}
+
+impl From<WritePageGuard> for PageMut {
+    fn from(guard: WritePageGuard) -> PageMut {
+        let range = 0..guard.content.len();
+        PageMut { guard, range }
+    }
+}
+
+impl Deref for PageMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.guard.content[self.range.clone()]
+    }
+}
+
+impl DerefMut for PageMut {
+    // goes through the guard, marking the frame dirty
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.guard.content[self.range.clone()]
+    }
+}
@@ -1,8 +1,59 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    io,
+    ops::AddAssign,
+    sync::{Arc, Mutex},
+};
 
-use crate::storage::page::PageID;
+use serde::{de::DeserializeOwned, Serialize};
 
-use super::scheduler::DiskManager;
+use crate::{
+    error::{Error, Result},
+    storage::{file::StorageBackend, page::PageID},
+    wal::{Lsn, TxnId},
+};
+
+use super::{
+    cache::PageTable,
+    guard::{PageMut, PageRef, ReadPageGuard, WritePageGuard},
+    replacer::ReplacementPolicy,
+    scheduler::{page_range, DiskManager, Durability, FlushAttempt, FLUSH_LATCH_TIMEOUT},
+    writer::{BackgroundWriter, WriterConfig},
+};
+
+// bytes in front of a value stored with `write_typed` holding
+// the length of its encoding
+const TYPED_LEN_SIZE: usize = 4;
+
+/// Summary of a flush, returned by the `BufferPoolManager` flush methods
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushReport {
+    pub pages_written: usize,
+    // clean pages that did not need a write
+    pub pages_skipped: usize,
+}
+
+impl FlushReport {
+    fn written() -> FlushReport {
+        FlushReport {
+            pages_written: 1,
+            ..FlushReport::default()
+        }
+    }
+
+    fn skipped() -> FlushReport {
+        FlushReport {
+            pages_skipped: 1,
+            ..FlushReport::default()
+        }
+    }
+}
+
+impl AddAssign for FlushReport {
+    fn add_assign(&mut self, other: FlushReport) {
+        self.pages_written += other.pages_written;
+        self.pages_skipped += other.pages_skipped;
+    }
+}
 
 #[allow(unused)]
 pub struct BufferPoolManager {
@@ -10,55 +61,408 @@ pub struct BufferPoolManager {
     /// max_frames     : max number of frames that can be held
     ///                  in the cache
     /// disk_manager   : Reference to the DiskManager
+    /// writer         : background writer, when started
+    /// page_table     : pins cached pages without locking the
+    ///                  DiskManager, when the replacer allows it
     max_frames: usize,
     pub disk_manager: Arc<Mutex<DiskManager>>,
+    writer: Option<BackgroundWriter>,
+    page_table: PageTable,
 }
 
 impl BufferPoolManager {
-    pub fn new(max_frames: usize, db_file: &str) -> BufferPoolManager {
-        let disk_manager = DiskManager::new(max_frames, db_file);
+    pub fn new(max_frames: usize, db_file: &str) -> Result<BufferPoolManager> {
+        BufferPoolManager::new_with_policy(max_frames, db_file, ReplacementPolicy::default())
+    }
+
+    /// Creates a pool evicting frames with the given replacement policy
+    pub fn new_with_policy(
+        max_frames: usize,
+        db_file: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<BufferPoolManager> {
+        let disk_manager = DiskManager::new(max_frames, db_file, policy)?;
+
+        Ok(BufferPoolManager::with_disk_manager(
+            max_frames,
+            disk_manager,
+        ))
+    }
+
+    /// Reopens a database previously closed with `BufferPoolManager::close`
+    pub fn open(max_frames: usize, db_file: &str) -> Result<BufferPoolManager> {
+        BufferPoolManager::open_with_policy(max_frames, db_file, ReplacementPolicy::default())
+    }
+
+    pub fn open_with_policy(
+        max_frames: usize,
+        db_file: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<BufferPoolManager> {
+        let disk_manager = DiskManager::open(max_frames, db_file, policy)?;
 
+        Ok(BufferPoolManager::with_disk_manager(
+            max_frames,
+            disk_manager,
+        ))
+    }
+
+    /// Creates a pool storing its pages on `backend`, see
+    /// `DiskManager::create_with_backend`
+    pub fn new_with_backend(
+        max_frames: usize,
+        backend: Arc<dyn StorageBackend>,
+        db_path: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<BufferPoolManager> {
+        let disk_manager = DiskManager::create_with_backend(max_frames, backend, db_path, policy)?;
+
+        Ok(BufferPoolManager::with_disk_manager(
+            max_frames,
+            disk_manager,
+        ))
+    }
+
+    /// Reopens a database stored on `backend`, see
+    /// `DiskManager::open_with_backend`
+    pub fn open_with_backend(
+        max_frames: usize,
+        backend: Arc<dyn StorageBackend>,
+        db_path: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<BufferPoolManager> {
+        let disk_manager = DiskManager::open_with_backend(max_frames, backend, db_path, policy)?;
+
+        Ok(BufferPoolManager::with_disk_manager(
+            max_frames,
+            disk_manager,
+        ))
+    }
+
+    fn with_disk_manager(max_frames: usize, disk_manager: DiskManager) -> BufferPoolManager {
         BufferPoolManager {
             max_frames,
+            page_table: disk_manager.page_table(),
             disk_manager: Arc::new(Mutex::new(disk_manager)),
+            writer: None,
         }
     }
 
-    pub fn new_page(&mut self) {
-        self.disk_manager.lock().unwrap().new_page();
+    /// Writes back cached pages and persists the page directory,
+    /// the background writer is stopped first
+    pub fn close(&mut self) -> Result<()> {
+        self.stop_background_writer();
+        self.disk_manager.lock().unwrap().close()
     }
 
-    pub fn read_page(&mut self, page_id: PageID) {
-        let mut disk_manager = self.disk_manager.lock().unwrap();
-        let page = disk_manager.read_page(page_id);
+    /// Starts a thread writing back dirty frames close to eviction,
+    /// replaces the writer if one is already running
+    pub fn start_background_writer(&mut self, config: WriterConfig) {
+        self.stop_background_writer();
+        self.writer = Some(BackgroundWriter::start(
+            Arc::clone(&self.disk_manager),
+            config,
+        ));
+    }
+
+    /// Stops the background writer, returns the number of pages
+    /// it wrote back
+    pub fn stop_background_writer(&mut self) -> u64 {
+        match self.writer.take() {
+            Some(mut writer) => {
+                writer.stop();
+                writer.pages_written()
+            }
+            None => 0,
+        }
+    }
+
+    /// Kind of the write error that put the pool in read only mode,
+    /// see `DiskManager::read_only`
+    pub fn read_only(&self) -> Option<io::ErrorKind> {
+        self.disk_manager.lock().unwrap().read_only()
     }
 
-    pub fn delete_page(&mut self, page_id: PageID) -> Result<(), Box<dyn std::error::Error>> {
+    /// Leaves read only mode once the cause of the failed writes is
+    /// fixed, see `DiskManager::resume_writes`
+    pub fn resume_writes(&self) {
+        self.disk_manager.lock().unwrap().resume_writes()
+    }
+
+    /// Writes pages through a double write buffer from now on, see
+    /// `DiskManager::enable_double_write`
+    pub fn enable_double_write(&self) -> Result<()> {
+        self.disk_manager.lock().unwrap().enable_double_write()
+    }
+
+    /// Changes when written pages reach stable storage, see
+    /// `DiskManager::set_durability`
+    pub fn set_durability(&self, durability: Durability) -> Result<()> {
+        self.disk_manager.lock().unwrap().set_durability(durability)
+    }
+
+    /// Starts a transaction, its logged page writes are undone
+    /// unless it commits
+    pub fn begin(&self) -> Result<TxnId> {
+        self.disk_manager.lock().unwrap().wal().begin()
+    }
+
+    /// Commits a transaction, returns once the commit is durable
+    pub fn commit(&self, txn_id: TxnId) -> Result<()> {
+        let wal = self.disk_manager.lock().unwrap().wal();
+        wal.commit(txn_id)?;
+        Ok(())
+    }
+
+    /// Rolls back a transaction, guards on pages it changed have
+    /// to be dropped first
+    pub fn abort(&self, txn_id: TxnId) -> Result<()> {
+        self.disk_manager.lock().unwrap().abort(txn_id)
+    }
+
+    /// Takes a fuzzy checkpoint, bounding how much of the log recovery
+    /// has to read. Pages are not written back and readers and writers
+    /// keep going, the disk manager is only held while the directory
+    /// and the master record are persisted. Returns the checkpoint LSN
+    pub fn checkpoint(&self) -> Result<Lsn> {
+        let wal = self.disk_manager.lock().unwrap().wal();
+        let checkpoint = wal.begin_checkpoint()?;
+        let lsn = checkpoint.0;
+
+        // syncs the db file as well, so pages written back before the
+        // begin record, which are missing from its dirty page table,
+        // are durable before the checkpoint is
+        self.disk_manager.lock().unwrap().persist_directory()?;
+        wal.end_checkpoint(checkpoint)?;
+        self.disk_manager.lock().unwrap().write_master_record(lsn)?;
+
+        Ok(lsn)
+    }
+
+    pub fn new_page(&mut self) -> Result<PageID> {
+        self.disk_manager.lock().unwrap().new_page()
+    }
+
+    /// Content of a page, borrowed from its frame without copying.
+    /// The page stays pinned and latched until the result is dropped
+    pub fn read_page(&self, page_id: PageID) -> Result<PageRef> {
+        Ok(self.fetch_page_read(page_id)?.into())
+    }
+
+    /// Mutable content of a page, see `BufferPoolManager::read_page`.
+    /// The frame is marked dirty once it is modified
+    pub fn write_page(&self, page_id: PageID) -> Result<PageMut> {
+        Ok(self.fetch_page_write(page_id)?.into())
+    }
+
+    /// `len` bytes of a page starting at `offset`, borrowed like
+    /// `BufferPoolManager::read_page`
+    pub fn read_at(&self, page_id: PageID, offset: usize, len: usize) -> Result<PageRef> {
+        self.fetch_page_read(page_id)?.into_bytes(offset, len)
+    }
+
+    /// Overwrites the bytes of a page at `offset`, the change is not
+    /// logged and the frame is only marked dirty
+    pub fn write_at(&self, page_id: PageID, offset: usize, bytes: &[u8]) -> Result<()> {
+        let mut page = self
+            .fetch_page_write(page_id)?
+            .into_bytes(offset, bytes.len())?;
+        page.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Decodes a value stored with `BufferPoolManager::write_typed`
+    pub fn read_typed<T: DeserializeOwned>(&self, page_id: PageID) -> Result<T> {
+        let page = self.read_page(page_id)?;
+        let (prefix, content) = page.split_at(TYPED_LEN_SIZE);
+        let len = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+        let Some(bytes) = content.get(..len) else {
+            return Err(Error::Corruption(format!(
+                "page {page_id} holds no value of {len} bytes"
+            )));
+        };
+
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    /// Stores `value` encoded with bincode at the start of the page,
+    /// behind its length. Fails with `Error::OutOfPage` and leaves the
+    /// page as is when the encoding does not fit. Like
+    /// `BufferPoolManager::write_at` the change is not logged
+    pub fn write_typed<T: Serialize + ?Sized>(&self, page_id: PageID, value: &T) -> Result<()> {
+        let len = bincode::serialized_size(value)? as usize;
+        let range = page_range(page_id, 0, TYPED_LEN_SIZE + len)?;
+
+        let mut page = self.write_page(page_id)?;
+        let (prefix, content) = page[range].split_at_mut(TYPED_LEN_SIZE);
+        prefix.copy_from_slice(&(len as u32).to_le_bytes());
+        bincode::serialize_into(content, value)?;
+        Ok(())
+    }
+
+    pub fn delete_page(&mut self, page_id: PageID) -> Result<()> {
         self.disk_manager.lock().unwrap().delete_page(page_id)
     }
 
-    pub fn flush_page_unsafe(page_id: PageID) -> Result<(), Box<dyn std::error::Error>> {
-        unimplemented!()
+    /// Pins the page and takes a shared latch on its frame, both
+    /// are released when the guard is dropped
+    pub fn fetch_page_read(&self, page_id: PageID) -> Result<ReadPageGuard> {
+        // a cached page only needs its reference bit set, the disk
+        // manager is not locked when the replacer allows it
+        let pinned = self.page_table.pin_frame(page_id);
+        let (frame, pins) = match pinned {
+            Some(pinned) => pinned,
+            None => self.disk_manager.lock().unwrap().pin_page(page_id)?,
+        };
+        // the latch is taken after releasing the disk manager so a
+        // writer holding the frame can still reach the pool
+        Ok(ReadPageGuard::new(page_id, frame, pins))
     }
 
-    pub fn flush_page(page_id: PageID) -> Result<(), Box<dyn std::error::Error>> {
-        unimplemented!()
+    /// Pins the page and takes an exclusive latch on its frame, the
+    /// frame is marked dirty once it is modified through the guard
+    pub fn fetch_page_write(&self, page_id: PageID) -> Result<WritePageGuard> {
+        let mut disk_manager = self.disk_manager.lock().unwrap();
+        disk_manager.check_writable()?;
+        let (frame, pins) = disk_manager.pin_page(page_id)?;
+        let wal = disk_manager.wal();
+        let scheduler = disk_manager.scheduler();
+        drop(disk_manager);
+
+        Ok(WritePageGuard::new(page_id, frame, pins, wal, scheduler))
     }
 
-    pub fn flush_all_pages_unsafe() {
-        unimplemented!()
+    /// Writes a cached page back to disk if it is dirty, the frame
+    /// stays latched for reads until it is written. Pages that are clean
+    /// or not cached are already on disk and nothing is written
+    ///
+    /// a writer holding the frame latch is waited for up to
+    /// `FLUSH_LATCH_TIMEOUT`, then `Error::PagePinned` is returned.
+    /// This is always the case while the caller holds a guard on the
+    /// page itself, `WritePageGuard::flush_page` flushes through one
+    pub fn flush_page(&self, page_id: PageID) -> Result<FlushReport> {
+        let flush = loop {
+            let attempt = self.disk_manager.lock().unwrap().schedule_flush(page_id)?;
+            match attempt {
+                FlushAttempt::Scheduled(flush) => break flush,
+                FlushAttempt::NotCached => return Ok(FlushReport::default()),
+                FlushAttempt::Clean => return Ok(FlushReport::skipped()),
+                // wait for the latch with the disk manager released
+                FlushAttempt::Latched(frame) => {
+                    if frame.try_write_for(FLUSH_LATCH_TIMEOUT).is_none() {
+                        return Err(Error::PagePinned(page_id));
+                    }
+                }
+                FlushAttempt::LogBehind(lsn) => {
+                    let wal = self.disk_manager.lock().unwrap().wal();
+                    wal.flush(lsn)?;
+                }
+            }
+        };
+
+        flush.wait()?;
+        Ok(FlushReport::written())
     }
 
-    pub fn flush_all_page() {
-        unimplemented!()
+    /// Writes a cached page back without taking its frame latch, lets
+    /// a caller holding the page's `WritePageGuard` flush it
+    ///
+    /// # Safety
+    ///
+    /// no other thread may access the page while it is flushed and
+    /// no reference into the frame may be held across the call
+    pub unsafe fn flush_page_unsafe(&self, page_id: PageID) -> Result<FlushReport> {
+        let attempt = unsafe {
+            self.disk_manager
+                .lock()
+                .unwrap()
+                .schedule_flush_unlatched(page_id)?
+        };
+        let flush = match attempt {
+            FlushAttempt::Scheduled(flush) => flush,
+            FlushAttempt::Clean => return Ok(FlushReport::skipped()),
+            _ => return Ok(FlushReport::default()),
+        };
+
+        // SAFETY: the caller guarantees there is no concurrent access
+        unsafe { flush.wait_unlatched()? };
+        Ok(FlushReport::written())
+    }
+
+    /// Writes back every cached page, see `BufferPoolManager::flush_page`
+    pub fn flush_all_page(&self) -> Result<FlushReport> {
+        let mut report = FlushReport::default();
+
+        for page_id in self.cached_pages() {
+            report += self.flush_page(page_id)?;
+        }
+
+        self.sync_flushed()?;
+        Ok(report)
+    }
+
+    /// Writes back every cached page without taking the frame latches
+    ///
+    /// # Safety
+    ///
+    /// no other thread may access any page while they are flushed and
+    /// no reference into a frame may be held across the call
+    pub unsafe fn flush_all_pages_unsafe(&self) -> Result<FlushReport> {
+        let mut report = FlushReport::default();
+
+        for page_id in self.cached_pages() {
+            report += unsafe { self.flush_page_unsafe(page_id)? };
+        }
+
+        self.sync_flushed()?;
+        Ok(report)
+    }
+
+    // every write of a flush has completed, under `SyncOnFlushAll`
+    // they are synced without holding the disk manager
+    fn sync_flushed(&self) -> Result<()> {
+        let scheduler = {
+            let disk_manager = self.disk_manager.lock().unwrap();
+            if disk_manager.durability() != Durability::SyncOnFlushAll {
+                return Ok(());
+            }
+            disk_manager.scheduler()
+        };
+        scheduler.sync_data()?;
+        Ok(())
+    }
+
+    fn cached_pages(&self) -> Vec<PageID> {
+        self.disk_manager.lock().unwrap().cache.page_ids()
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
-    use std::fs::{self, OpenOptions};
+    use std::{
+        fs::{self, OpenOptions},
+        io::{ErrorKind, Write},
+        iter,
+        path::Path,
+        sync::{mpsc, Arc},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::storage::{
+        fault::{Fault, FaultTarget, FaultyBackend},
+        file::{FileBackend, StorageBackend},
+        header::HEADER_SIZE,
+        memory::MemoryBackend,
+        page::{FRAME_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE},
+    };
 
-    use crate::storage::page::FRAME_SIZE;
+    use crate::{
+        buffer::{replacer::ReplacementPolicy, scheduler::Durability, writer::WriterConfig},
+        wal::{LogManager, INVALID_TXN, LOG_EXTENSION},
+        Error,
+    };
 
     use super::BufferPoolManager;
 
@@ -68,7 +472,7 @@ mod test {
         const FILE_PATH: &str = "/tmp/create_bpm_manger_test.db";
         let _ = fs::remove_file(FILE_PATH);
 
-        let _bpm = BufferPoolManager::new(3, FILE_PATH);
+        let _bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
 
         let file = OpenOptions::new()
             .read(true)
@@ -77,7 +481,7 @@ mod test {
 
         let file_size = file.metadata().unwrap().len();
 
-        assert_eq!(file_size, 0);
+        assert_eq!(file_size, HEADER_SIZE);
 
         fs::remove_file(FILE_PATH).unwrap();
     }
@@ -88,10 +492,10 @@ mod test {
         const FILE_PATH: &str = "/tmp/test_single_page_alloc.db";
         let _ = fs::remove_file(FILE_PATH);
 
-        let bpm = BufferPoolManager::new(3, FILE_PATH);
+        let bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
 
         let mut writer = bpm.disk_manager.lock().unwrap();
-        writer.new_page();
+        writer.new_page().unwrap();
         drop(writer);
 
         let file = OpenOptions::new()
@@ -102,7 +506,7 @@ mod test {
         let file_size = file.metadata().unwrap().len();
         println!("[TEST][DEBUG][BPM] new page alloc -> filesize {file_size}");
 
-        assert_eq!(file_size, FRAME_SIZE);
+        assert_eq!(file_size, HEADER_SIZE + PAGE_SIZE);
 
         fs::remove_file(FILE_PATH).unwrap();
     }
@@ -113,12 +517,12 @@ mod test {
         const FILE_PATH: &str = "/tmp/test_multiple_page_alloc.db";
         let _ = fs::remove_file(FILE_PATH);
 
-        let mut bpm = BufferPoolManager::new(3, FILE_PATH);
+        let mut bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
 
-        bpm.new_page();
-        bpm.new_page();
-        bpm.new_page();
-        bpm.new_page();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
 
         let file = OpenOptions::new()
             .read(true)
@@ -128,15 +532,19 @@ mod test {
         let file_size = file.metadata().unwrap().len();
         println!("[TEST][DEBUG][BPM] new page alloc -> filesize {file_size}");
 
-        assert_eq!(file_size, FRAME_SIZE * 4);
+        assert_eq!(file_size, HEADER_SIZE + PAGE_SIZE * 4);
+        // pages are whole blocks, every one starts block aligned
+        assert_eq!(file_size % PAGE_SIZE, 0);
 
         let mut writer = bpm.disk_manager.lock().unwrap();
 
-        let frame_content = writer.read_page(2);
-        assert_eq!(frame_content.len(), FRAME_SIZE as usize);
+        let mut frame_content = [1; FRAME_SIZE as usize];
+        writer.read_at(2, 0, &mut frame_content).unwrap();
+        assert!(frame_content.iter().all(|byte| *byte == 0));
 
-        let _ = writer.read_page(1); // this will be fetched out of memory
-                                     // and will push page 3 out of memory
+        let mut first = [0; 8];
+        writer.read_at(1, 0, &mut first).unwrap(); // this will be fetched out of memory
+                                                   // and will push page 3 out of memory
 
         drop(writer);
 
@@ -148,9 +556,9 @@ mod test {
         const FILE_PATH: &str = "/tmp/test_delete_pages.db";
         let _ = fs::remove_file(FILE_PATH);
 
-        let mut bpm = BufferPoolManager::new(3, FILE_PATH);
-        bpm.new_page();
-        bpm.new_page();
+        let mut bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
 
         let mut delete_result = bpm.delete_page(3);
         assert_eq!(delete_result.is_err(), true);
@@ -166,12 +574,35 @@ mod test {
             Err(e) => println!("{}", e),
         }
 
-        let db_size = bpm.disk_manager.lock().unwrap().get_db_size();
-        assert_eq!(db_size, FRAME_SIZE * 2);
+        let db_size = bpm.disk_manager.lock().unwrap().get_db_size().unwrap();
+        assert_eq!(db_size, HEADER_SIZE + PAGE_SIZE * 2);
+
+        bpm.new_page().unwrap();
+        let db_size = bpm.disk_manager.lock().unwrap().get_db_size().unwrap();
+        assert_eq!(db_size, HEADER_SIZE + PAGE_SIZE * 2);
+    }
+
+    #[test]
+    fn test_new_page_forces_log_on_reuse() {
+        const FILE_PATH: &str = "/tmp/test_new_page_forces_log_on_reuse.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
+        let wal = bpm.disk_manager.lock().unwrap().wal();
 
-        bpm.new_page();
-        let db_size = bpm.disk_manager.lock().unwrap().get_db_size();
-        assert_eq!(db_size, FRAME_SIZE * 2);
+        // growing the file does not wait for the log
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        assert!(!wal.is_durable(wal.next_lsn() - 1));
+
+        // taking the slot of a deleted page does
+        bpm.delete_page(2).unwrap();
+        bpm.new_page().unwrap();
+        assert!(wal.is_durable(wal.next_lsn() - 1));
+
+        bpm.close().unwrap();
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
     }
 
     #[test]
@@ -179,24 +610,24 @@ mod test {
         const FILE_PATH: &str = "/tmp/single_page_write_test.db";
         let _ = fs::remove_file(FILE_PATH);
 
-        let mut bpm = BufferPoolManager::new(3, FILE_PATH);
-        bpm.new_page();
-        bpm.new_page();
+        let mut bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
 
-        let mut write_res = bpm
-            .disk_manager
-            .lock()
-            .unwrap()
-            .write_page(3, Box::new([1; FRAME_SIZE as usize]));
+        let mut write_res =
+            bpm.disk_manager
+                .lock()
+                .unwrap()
+                .write_at(3, 0, &[1; FRAME_SIZE as usize]);
         assert_eq!(write_res.is_err(), true);
 
-        let new_frame = Box::new([1; FRAME_SIZE as usize]);
+        let new_frame = [1; FRAME_SIZE as usize];
         dbg!(&new_frame[0], &new_frame.len());
 
-        write_res = bpm.disk_manager.lock().unwrap().write_page(1, new_frame);
+        write_res = bpm.disk_manager.lock().unwrap().write_at(1, 0, &new_frame);
         assert_eq!(write_res.is_err(), false);
 
-        let frame = bpm.disk_manager.lock().unwrap().read_page(1);
+        let frame = bpm.read_page(1).unwrap();
         assert_eq!(
             frame.iter().map(|v| v.to_owned() as u64).sum::<u64>(),
             FRAME_SIZE
@@ -208,11 +639,11 @@ mod test {
         const FILE_PATH: &str = "/tmp/test_single_page_flush.db";
         let _ = fs::remove_dir(FILE_PATH);
 
-        let mut bpm = BufferPoolManager::new(1, FILE_PATH);
-        bpm.new_page();
+        let mut bpm = BufferPoolManager::new(1, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
 
-        let new_frame = Box::new([1; FRAME_SIZE as usize]);
-        let write_res = bpm.disk_manager.lock().unwrap().write_page(1, new_frame);
+        let new_frame = [1; FRAME_SIZE as usize];
+        let write_res = bpm.disk_manager.lock().unwrap().write_at(1, 0, &new_frame);
         assert_eq!(write_res.is_err(), false);
 
         dbg!(
@@ -223,7 +654,6 @@ mod test {
                 .lookup_frame(1)
                 .unwrap()
                 .read()
-                .unwrap()
                 .content
                 .iter()
                 .map(|v| v.to_owned() as u64)
@@ -231,7 +661,7 @@ mod test {
             FRAME_SIZE
         );
 
-        bpm.new_page();
+        bpm.new_page().unwrap();
 
         assert_eq!(
             bpm.disk_manager
@@ -243,10 +673,885 @@ mod test {
             false
         );
 
-        let read_res = bpm.disk_manager.lock().unwrap().read_page(1);
+        let read_res = bpm.read_page(1).unwrap();
         assert_eq!(
             read_res.iter().map(|v| v.to_owned() as u64).sum::<u64>(),
             FRAME_SIZE
         );
     }
+
+    #[test]
+    fn test_reopen_database() {
+        const FILE_PATH: &str = "/tmp/test_reopen_database.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.delete_page(2).unwrap();
+
+        bpm.disk_manager
+            .lock()
+            .unwrap()
+            .write_at(3, 0, &[7; FRAME_SIZE as usize])
+            .unwrap();
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let mut bpm = BufferPoolManager::open(2, FILE_PATH).unwrap();
+        let content = bpm.read_page(3).unwrap();
+        assert!(content.iter().all(|byte| *byte == 7));
+
+        // the slot freed by deleting page 2 now holds the
+        // persisted directory, so the file grows
+        bpm.new_page().unwrap();
+        let db_size = bpm.disk_manager.lock().unwrap().get_db_size().unwrap();
+        assert_eq!(db_size, HEADER_SIZE + PAGE_SIZE * 4);
+        assert!(bpm.delete_page(4).is_ok());
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_open_rejects_foreign_file() {
+        const FILE_PATH: &str = "/tmp/test_open_rejects_foreign_file.db";
+        fs::write(FILE_PATH, [0xAB; PAGE_SIZE as usize * 2]).unwrap();
+
+        let open_res = BufferPoolManager::open(2, FILE_PATH);
+        assert!(matches!(open_res, Err(Error::InvalidFile(_))));
+
+        // a failed open must not touch the file
+        let file_size = fs::metadata(FILE_PATH).unwrap().len();
+        assert_eq!(file_size, PAGE_SIZE * 2);
+
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_page_guards_pin_frames() {
+        const FILE_PATH: &str = "/tmp/test_page_guards_pin_frames.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+
+        let mut write_guard = bpm.fetch_page_write(1).unwrap();
+        assert_eq!(write_guard.dirty, false);
+        write_guard.content[0] = 42;
+        assert_eq!(write_guard.dirty, true);
+
+        let read_guard = bpm.fetch_page_read(2).unwrap();
+        assert_eq!(read_guard.page_id(), 2);
+
+        // both frames are pinned, nothing can be evicted
+        assert!(bpm.delete_page(1).is_err());
+        bpm.new_page().unwrap();
+        assert!(bpm.fetch_page_read(3).is_err());
+
+        drop(write_guard);
+        drop(read_guard);
+
+        // page 1 is the least recently used and gets written back
+        let read_guard = bpm.fetch_page_read(3).unwrap();
+        drop(read_guard);
+        assert_eq!(bpm.read_page(1).unwrap()[0], 42);
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_latched_page_does_not_block_pool() {
+        const FILE_PATH: &str = "/tmp/test_latched_page_does_not_block_pool.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+
+        let (latched, wait_latched) = mpsc::channel();
+        thread::scope(|scope| {
+            let bpm = &bpm;
+            let writer = scope.spawn(move || {
+                let mut guard = bpm.fetch_page_write(1).unwrap();
+                latched.send(()).unwrap();
+                // give the reader time to block on the latch of page 1,
+                // the pool has to stay reachable meanwhile
+                thread::sleep(Duration::from_millis(50));
+                let other = bpm.fetch_page_read(2).unwrap();
+                guard.content[0] = 5;
+                drop(other);
+            });
+
+            wait_latched.recv().unwrap();
+            let reader = bpm.fetch_page_read(1).unwrap();
+            assert_eq!(reader.content[0], 5);
+            drop(reader);
+            writer.join().unwrap();
+        });
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_clock_reads_bypass_disk_manager() {
+        const FILE_PATH: &str = "/tmp/test_clock_reads_bypass_disk_manager.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm =
+            BufferPoolManager::new_with_policy(4, FILE_PATH, ReplacementPolicy::Clock).unwrap();
+        for page_id in 1..=8 {
+            bpm.new_page().unwrap();
+            bpm.fetch_page_write(page_id).unwrap().content[0] = page_id as u8;
+        }
+
+        // a cached page is pinned while the disk manager is held
+        let disk_manager = bpm.disk_manager.lock().unwrap();
+        thread::scope(|scope| {
+            let reader = scope.spawn(|| bpm.fetch_page_read(8).unwrap().content[0]);
+            assert_eq!(reader.join().unwrap(), 8);
+        });
+        drop(disk_manager);
+
+        // readers racing with evictions always see their own page
+        thread::scope(|scope| {
+            for worker in 0..4u32 {
+                let bpm = &bpm;
+                scope.spawn(move || {
+                    for round in 0..200 {
+                        let page_id = (worker * 7 + round) % 8 + 1;
+                        let page = bpm.fetch_page_read(page_id).unwrap();
+                        assert_eq!(page.content[0], page_id as u8);
+                    }
+                });
+            }
+        });
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_flush_reports_written_pages() {
+        const FILE_PATH: &str = "/tmp/test_flush_reports_written_pages.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(3, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+
+        let on_disk = |page_id: u64| {
+            let offset = (HEADER_SIZE + (page_id - 1) * PAGE_SIZE + PAGE_HEADER_SIZE) as usize;
+            fs::read(FILE_PATH).unwrap()[offset]
+        };
+
+        let mut write_guard = bpm.fetch_page_write(1).unwrap();
+        write_guard.content[0] = 7;
+        drop(write_guard);
+        assert_eq!(on_disk(1), 0);
+
+        let report = bpm.flush_page(1).unwrap();
+        assert_eq!(report.pages_written, 1);
+        assert_eq!(on_disk(1), 7);
+
+        // the latch is already held by the caller, only the unsafe
+        // variant can flush the page
+        let mut write_guard = bpm.fetch_page_write(2).unwrap();
+        write_guard.content[0] = 9;
+        let report = unsafe { bpm.flush_page_unsafe(2).unwrap() };
+        assert_eq!(report.pages_written, 1);
+        assert_eq!(on_disk(2), 9);
+        drop(write_guard);
+
+        // both pages were written back and are clean again
+        let report = bpm.flush_all_page().unwrap();
+        assert_eq!((report.pages_written, report.pages_skipped), (0, 2));
+
+        bpm.fetch_page_write(1).unwrap().content[1] = 3;
+        let report = unsafe { bpm.flush_all_pages_unsafe().unwrap() };
+        assert_eq!((report.pages_written, report.pages_skipped), (1, 1));
+        assert!(bpm.flush_page(5).is_err());
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_clean_frames_are_not_written_back() {
+        const FILE_PATH: &str = "/tmp/test_clean_frames_are_not_written_back.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        for _ in 0..3 {
+            bpm.new_page().unwrap();
+        }
+        let avoided = || bpm.disk_manager.lock().unwrap().avoided_writes();
+        let before = avoided();
+
+        // a read only scan evicts without writing anything back
+        for page_id in [1, 2, 3, 1] {
+            drop(bpm.fetch_page_read(page_id).unwrap());
+        }
+        assert_eq!(avoided(), before + 4);
+
+        // a modified frame is still written back when evicted
+        bpm.fetch_page_write(1).unwrap().content[0] = 5;
+        drop(bpm.fetch_page_read(2).unwrap());
+        drop(bpm.fetch_page_read(3).unwrap());
+        assert_eq!(avoided(), before + 5);
+        assert_eq!(bpm.read_page(1).unwrap()[0], 5);
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_background_writer() {
+        const FILE_PATH: &str = "/tmp/test_background_writer.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(4, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+
+        let on_disk = |page_id: u64| {
+            let offset = (HEADER_SIZE + (page_id - 1) * PAGE_SIZE + PAGE_HEADER_SIZE) as usize;
+            fs::read(FILE_PATH).unwrap()[offset]
+        };
+
+        bpm.fetch_page_write(1).unwrap().content[0] = 4;
+        // pinned frames are left alone
+        let mut pinned = bpm.fetch_page_write(2).unwrap();
+        pinned.content[0] = 6;
+
+        bpm.start_background_writer(WriterConfig {
+            interval: Duration::from_millis(5),
+            max_pages: 4,
+        });
+        let started = Instant::now();
+        while on_disk(1) != 4 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(on_disk(2), 0);
+
+        assert_eq!(bpm.stop_background_writer(), 1);
+        assert_eq!(pinned.dirty, true);
+        drop(pinned);
+
+        // page 1 is clean now, only page 2 is left to flush
+        let report = bpm.flush_all_page().unwrap();
+        assert_eq!((report.pages_written, report.pages_skipped), (1, 1));
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_failed_background_write_keeps_page() {
+        const FILE_PATH: &str = "/tmp/test_failed_background_write_keeps_page.db";
+
+        // writes of page 1 stall and then fail
+        let memory = Arc::new(MemoryBackend::new());
+        let failing = Arc::new(FaultyBackend::new(memory));
+        let slow = Arc::new(FaultyBackend::new(failing.clone()));
+        let policy = ReplacementPolicy::default();
+        let mut bpm =
+            BufferPoolManager::new_with_backend(2, slow.clone(), FILE_PATH, policy).unwrap();
+        for _ in 0..3 {
+            bpm.new_page().unwrap();
+        }
+        bpm.fetch_page_write(1).unwrap().content[..4].copy_from_slice(b"kept");
+
+        let page_offset = FaultTarget::Offset(HEADER_SIZE);
+        slow.inject(page_offset, Fault::Delay(Duration::from_millis(100)));
+        failing.inject(page_offset, Fault::Fail(ErrorKind::TimedOut));
+        let writes = slow.writes();
+        bpm.start_background_writer(WriterConfig {
+            interval: Duration::from_millis(5),
+            max_pages: 2,
+        });
+        let started = Instant::now();
+        while slow.writes() == writes {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // the frame being written can not be evicted, the other one is
+        drop(bpm.fetch_page_read(2).unwrap());
+        drop(bpm.fetch_page_read(3).unwrap());
+        assert_eq!(bpm.stop_background_writer(), 0);
+
+        slow.clear();
+        failing.clear();
+        assert_eq!(&bpm.read_page(1).unwrap()[..4], b"kept");
+        assert_eq!(bpm.flush_page(1).unwrap().pages_written, 1);
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_page() {
+        const FILE_PATH: &str = "/tmp/test_failed_flush_keeps_page.db";
+
+        // writes of page 1 stall and then fail
+        let memory = Arc::new(MemoryBackend::new());
+        let failing = Arc::new(FaultyBackend::new(memory));
+        let slow = Arc::new(FaultyBackend::new(failing.clone()));
+        let policy = ReplacementPolicy::default();
+        let mut bpm =
+            BufferPoolManager::new_with_backend(2, slow.clone(), FILE_PATH, policy).unwrap();
+        for _ in 0..3 {
+            bpm.new_page().unwrap();
+        }
+        bpm.fetch_page_write(1).unwrap().content[..4].copy_from_slice(b"kept");
+
+        let page_offset = FaultTarget::Offset(HEADER_SIZE);
+        slow.inject(page_offset, Fault::Delay(Duration::from_millis(100)));
+        failing.inject(page_offset, Fault::Fail(ErrorKind::TimedOut));
+        let writes = slow.writes();
+        thread::scope(|scope| {
+            let flush = scope.spawn(|| bpm.flush_page(1));
+            let started = Instant::now();
+            while slow.writes() == writes {
+                assert!(started.elapsed() < Duration::from_secs(5));
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            // the frame being written can not be evicted, the other one is
+            drop(bpm.fetch_page_read(2).unwrap());
+            drop(bpm.fetch_page_read(3).unwrap());
+            assert!(flush.join().unwrap().is_err());
+        });
+
+        slow.clear();
+        failing.clear();
+        assert_eq!(&bpm.read_page(1).unwrap()[..4], b"kept");
+        assert_eq!(bpm.flush_page(1).unwrap().pages_written, 1);
+        assert_eq!(bpm.flush_page(1).unwrap().pages_written, 0);
+    }
+
+    #[test]
+    fn test_flush_fails_while_caller_holds_latch() {
+        const FILE_PATH: &str = "/tmp/test_flush_fails_while_caller_holds_latch.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.fetch_page_write(1).unwrap().content[0] = 1;
+
+        // waiting for the latch would wait for ourselves
+        let guard = bpm.fetch_page_read(1).unwrap();
+        assert!(matches!(bpm.flush_page(1), Err(Error::PagePinned(1))));
+        drop(guard);
+        assert_eq!(bpm.flush_page(1).unwrap().pages_written, 1);
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_log_is_durable_before_page() {
+        const FILE_PATH: &str = "/tmp/test_log_is_durable_before_page.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        let wal = bpm.disk_manager.lock().unwrap().wal();
+
+        let mut guard = bpm.fetch_page_write(1).unwrap();
+        let lsn = guard.write_logged(INVALID_TXN, 10, &[1, 2, 3]).unwrap();
+        assert_eq!(guard.page_lsn, lsn);
+        assert_eq!(&guard.content[10..13], &[1, 2, 3]);
+        assert!(matches!(
+            guard.write_logged(INVALID_TXN, FRAME_SIZE as usize - 1, &[0, 0]),
+            Err(Error::OutOfPage { page_id: 1, .. })
+        ));
+        assert!(matches!(
+            guard.write_logged(INVALID_TXN, usize::MAX, &[0]),
+            Err(Error::OutOfPage { page_id: 1, .. })
+        ));
+        drop(guard);
+        assert!(!wal.is_durable(lsn));
+
+        // writing the page back forces the log first
+        bpm.flush_page(1).unwrap();
+        assert!(wal.is_durable(lsn));
+
+        let bytes = fs::read(FILE_PATH).unwrap();
+        let page = &bytes[HEADER_SIZE as usize..(HEADER_SIZE + PAGE_SIZE) as usize];
+        assert_eq!(page[..8], lsn.to_le_bytes());
+        assert_eq!(page[PAGE_HEADER_SIZE as usize + 10], 1);
+
+        // the page registration is logged ahead of the write
+        let records = wal.records().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].lsn, lsn);
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_page_fails_checksum() {
+        const FILE_PATH: &str = "/tmp/test_corrupt_page_fails_checksum.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.fetch_page_write(1).unwrap().content[..4].copy_from_slice(b"data");
+        bpm.fetch_page_write(2).unwrap().content[..4].copy_from_slice(b"more");
+        bpm.close().unwrap();
+        drop(bpm);
+
+        // flip a byte of the first page behind the pool's back
+        let mut bytes = fs::read(FILE_PATH).unwrap();
+        bytes[(HEADER_SIZE + PAGE_HEADER_SIZE) as usize + 1] ^= 0xff;
+        fs::write(FILE_PATH, bytes).unwrap();
+
+        let bpm = BufferPoolManager::open(2, FILE_PATH).unwrap();
+        let Err(error) = bpm.fetch_page_read(1) else {
+            panic!("corrupt page was handed out");
+        };
+        assert!(matches!(
+            error,
+            Error::ChecksumMismatch { page_id: 1, offset } if offset == HEADER_SIZE as usize
+        ));
+        assert_eq!(&bpm.fetch_page_read(2).unwrap().content[..4], b"more");
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_double_write_repairs_torn_page() {
+        const FILE_PATH: &str = "/tmp/test_double_write_repairs_torn_page.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.enable_double_write().unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.fetch_page_write(1).unwrap().content[..4].copy_from_slice(b"data");
+        bpm.fetch_page_write(2).unwrap().content[..4].copy_from_slice(b"more");
+        bpm.flush_all_page().unwrap();
+        bpm.fetch_page_write(1).unwrap().content[..5].copy_from_slice(b"newer");
+        bpm.flush_page(1).unwrap();
+        bpm.close().unwrap();
+        drop(bpm);
+
+        // only the first half of the last write of page 1 made it
+        let mut bytes = fs::read(FILE_PATH).unwrap();
+        let half = (HEADER_SIZE + PAGE_SIZE / 2) as usize;
+        bytes[half..half + PAGE_SIZE as usize / 2].fill(0xee);
+        fs::write(FILE_PATH, bytes).unwrap();
+
+        let bpm = BufferPoolManager::open(2, FILE_PATH).unwrap();
+        assert_eq!(&bpm.fetch_page_read(1).unwrap().content[..5], b"newer");
+        assert_eq!(&bpm.fetch_page_read(2).unwrap().content[..4], b"more");
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_errors_instead_of_panics() {
+        const FILE_PATH: &str = "/tmp/test_errors_instead_of_panics.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(1, FILE_PATH).unwrap();
+        assert!(matches!(bpm.read_page(7), Err(Error::PageNotFound(7))));
+        assert!(matches!(bpm.delete_page(7), Err(Error::PageNotFound(7))));
+
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        let _guard = bpm.fetch_page_read(1).unwrap();
+        assert!(matches!(bpm.fetch_page_read(2), Err(Error::NoFreeFrames)));
+        assert!(matches!(bpm.delete_page(1), Err(Error::PagePinned(1))));
+        drop(_guard);
+
+        bpm.close().unwrap();
+        assert!(matches!(bpm.fetch_page_read(1), Err(Error::Closed)));
+        assert!(matches!(bpm.new_page(), Err(Error::Closed)));
+        drop(bpm);
+
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_full_disk_keeps_dirty_frame() {
+        const FILE_PATH: &str = "/tmp/test_full_disk_keeps_dirty_frame.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(1, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.fetch_page_write(1).unwrap().content[..4].copy_from_slice(b"kept");
+
+        // every page write fails with ENOSPC from now on
+        let scheduler = bpm.disk_manager.lock().unwrap().scheduler();
+        let full = FileBackend::open(Path::new("/dev/full")).unwrap();
+        scheduler.set_write_file(Some(Arc::new(full)));
+
+        // evicting the dirty page fails, it stays cached
+        let Err(error) = bpm.fetch_page_read(2) else {
+            panic!("dirty page was evicted");
+        };
+        assert!(matches!(error, Error::ReadOnly(ErrorKind::StorageFull)));
+        assert_eq!(bpm.read_only(), Some(ErrorKind::StorageFull));
+        assert_eq!(&bpm.fetch_page_read(1).unwrap().content[..4], b"kept");
+        assert!(matches!(
+            bpm.fetch_page_write(1),
+            Err(Error::ReadOnly(ErrorKind::StorageFull))
+        ));
+        assert!(matches!(bpm.new_page(), Err(Error::ReadOnly(_))));
+
+        // space was freed
+        scheduler.set_write_file(None);
+        bpm.resume_writes();
+        assert_eq!(bpm.read_only(), None);
+        drop(bpm.fetch_page_read(2).unwrap());
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let bpm = BufferPoolManager::open(1, FILE_PATH).unwrap();
+        assert_eq!(&bpm.fetch_page_read(1).unwrap().content[..4], b"kept");
+        drop(bpm);
+
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_durability_modes() {
+        const FILE_PATH: &str = "/tmp/test_durability_modes.db";
+
+        for durability in [
+            Durability::NoSync,
+            Durability::SyncOnFlushAll,
+            Durability::SyncPerPage,
+            Durability::Dsync,
+        ] {
+            let _ = fs::remove_file(FILE_PATH);
+
+            // a single frame, so pages are written back on eviction too
+            let mut bpm = BufferPoolManager::new(1, FILE_PATH).unwrap();
+            bpm.set_durability(durability).unwrap();
+            assert_eq!(bpm.disk_manager.lock().unwrap().durability(), durability);
+            for page_id in 1..=3 {
+                assert_eq!(bpm.new_page().unwrap(), page_id);
+                bpm.fetch_page_write(page_id).unwrap().content[..4]
+                    .copy_from_slice(&page_id.to_le_bytes());
+            }
+            let report = bpm.flush_all_page().unwrap();
+            assert_eq!(report.pages_written, 1);
+            bpm.close().unwrap();
+            drop(bpm);
+
+            let bpm = BufferPoolManager::open(1, FILE_PATH).unwrap();
+            for page_id in 1..=3 {
+                let page = bpm.fetch_page_read(page_id).unwrap();
+                assert_eq!(page.content[..4], page_id.to_le_bytes(), "{durability:?}");
+            }
+            drop(bpm);
+        }
+
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        const FILE_PATH: &str = "/tmp/test_memory_backend.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let memory = Arc::new(MemoryBackend::new());
+        let policy = ReplacementPolicy::default();
+        let mut bpm =
+            BufferPoolManager::new_with_backend(1, memory.clone(), FILE_PATH, policy).unwrap();
+        for page_id in 1..=3 {
+            assert_eq!(bpm.new_page().unwrap(), page_id);
+            bpm.fetch_page_write(page_id).unwrap().content[..4]
+                .copy_from_slice(&page_id.to_le_bytes());
+        }
+        bpm.close().unwrap();
+        drop(bpm);
+
+        // the pages never touched the file system
+        assert!(!Path::new(FILE_PATH).exists());
+        assert!(memory.len().unwrap() >= HEADER_SIZE + PAGE_SIZE * 3);
+
+        let bpm = BufferPoolManager::open_with_backend(1, memory, FILE_PATH, policy).unwrap();
+        for page_id in 1..=3 {
+            let page = bpm.fetch_page_read(page_id).unwrap();
+            assert_eq!(page.content[..4], page_id.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_guard_flush_leaves_pool_unlocked() {
+        const FILE_PATH: &str = "/tmp/test_guard_flush_leaves_pool_unlocked.db";
+
+        let memory = Arc::new(MemoryBackend::new());
+        let policy = ReplacementPolicy::default();
+        let mut bpm =
+            BufferPoolManager::new_with_backend(2, memory.clone(), FILE_PATH, policy).unwrap();
+        bpm.new_page().unwrap();
+        let mut guard = bpm.fetch_page_write(1).unwrap();
+        guard.content[..4].copy_from_slice(b"kept");
+
+        // someone holds the pool, e.g. a flush about to try the latch
+        // of the guard, the guard still writes its page back
+        let disk_manager = bpm.disk_manager.lock().unwrap();
+        let (flushed, done) = mpsc::channel();
+        thread::spawn(move || {
+            let written = guard.flush_page().unwrap();
+            flushed.send((written, guard.dirty)).unwrap();
+        });
+        let (written, dirty) = done
+            .recv_timeout(Duration::from_secs(5))
+            .expect("guard flush waited for the pool");
+        assert!(written && !dirty);
+        drop(disk_manager);
+
+        assert_eq!(bpm.flush_all_page().unwrap().pages_written, 0);
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let bpm = BufferPoolManager::open_with_backend(2, memory, FILE_PATH, policy).unwrap();
+        assert_eq!(&bpm.fetch_page_read(1).unwrap().content[..4], b"kept");
+    }
+
+    #[test]
+    fn test_failed_new_page_is_unregistered() {
+        const FILE_PATH: &str = "/tmp/test_failed_new_page_is_unregistered.db";
+
+        let memory = Arc::new(MemoryBackend::new());
+        let faulty = Arc::new(FaultyBackend::new(memory));
+        let policy = ReplacementPolicy::default();
+        let mut bpm =
+            BufferPoolManager::new_with_backend(1, faulty.clone(), FILE_PATH, policy).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+
+        // every frame buffer is held, e.g. by pages being read
+        let mut disk_manager = bpm.disk_manager.lock().unwrap();
+        let held: Vec<_> = iter::from_fn(|| disk_manager.cache.frame_buffer().ok()).collect();
+        assert!(matches!(disk_manager.new_page(), Err(Error::NoFreeFrames)));
+        assert!(!disk_manager.contains_page(3));
+        drop(held);
+        drop(disk_manager);
+        assert_eq!(bpm.new_page().unwrap(), 3);
+
+        // reusing the slot of page 2 forces the log, which fails
+        bpm.delete_page(2).unwrap();
+        let log = faulty.faulty_sidecar(LOG_EXTENSION).unwrap();
+        log.inject(FaultTarget::Any, Fault::Fail(ErrorKind::StorageFull));
+        assert!(bpm.new_page().is_err());
+        let disk_manager = bpm.disk_manager.lock().unwrap();
+        assert!(!disk_manager.contains_page(4));
+        drop(disk_manager);
+
+        // the slot is handed out again once the log can be written
+        log.clear();
+        let page_id = bpm.new_page().unwrap();
+        assert_eq!(page_id, 5);
+        assert!(bpm.disk_manager.lock().unwrap().contains_page(page_id));
+    }
+
+    #[test]
+    fn test_injected_write_faults() {
+        const FILE_PATH: &str = "/tmp/test_injected_write_faults.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let memory = Arc::new(MemoryBackend::new());
+        let faulty = Arc::new(FaultyBackend::new(memory.clone()));
+        let policy = ReplacementPolicy::default();
+        let mut bpm =
+            BufferPoolManager::new_with_backend(1, faulty.clone(), FILE_PATH, policy).unwrap();
+        bpm.enable_double_write().unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.fetch_page_write(1).unwrap().content[..4].copy_from_slice(b"kept");
+
+        // writing page 1 back fails, it stays cached and dirty
+        let page_offset = FaultTarget::Offset(HEADER_SIZE);
+        faulty.inject(page_offset, Fault::Fail(ErrorKind::StorageFull));
+        assert!(matches!(
+            bpm.fetch_page_read(2),
+            Err(Error::ReadOnly(ErrorKind::StorageFull))
+        ));
+        assert_eq!(&bpm.fetch_page_read(1).unwrap().content[..4], b"kept");
+
+        // the next write of page 1 is torn, the double write buffer
+        // still has the whole page
+        faulty.clear();
+        faulty.inject(page_offset, Fault::Tear(PAGE_HEADER_SIZE as usize));
+        bpm.resume_writes();
+        bpm.flush_page(1).unwrap();
+        faulty.clear();
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let bpm = BufferPoolManager::open_with_backend(1, memory, FILE_PATH, policy).unwrap();
+        assert_eq!(&bpm.fetch_page_read(1).unwrap().content[..4], b"kept");
+    }
+
+    #[test]
+    fn test_injected_log_faults() {
+        const FILE_PATH: &str = "/tmp/test_injected_log_faults.db";
+
+        let memory = Arc::new(MemoryBackend::new());
+        let faulty = Arc::new(FaultyBackend::new(memory.clone()));
+        let policy = ReplacementPolicy::default();
+        let mut bpm =
+            BufferPoolManager::new_with_backend(1, faulty.clone(), FILE_PATH, policy).unwrap();
+        bpm.new_page().unwrap();
+        let log = faulty.faulty_sidecar(LOG_EXTENSION).unwrap();
+
+        // the commit can not be made durable, nor the page written
+        // back ahead of its log records
+        let txn_id = bpm.begin().unwrap();
+        let mut guard = bpm.fetch_page_write(1).unwrap();
+        guard.write_logged(txn_id, 0, b"logged").unwrap();
+        drop(guard);
+        log.inject(FaultTarget::Any, Fault::Fail(ErrorKind::StorageFull));
+        assert!(bpm.commit(txn_id).is_err());
+        assert!(bpm.flush_page(1).is_err());
+
+        log.clear();
+        assert_eq!(bpm.flush_page(1).unwrap().pages_written, 1);
+        drop(bpm);
+
+        // the log lives next to the db file in memory
+        assert!(!LogManager::log_path(FILE_PATH).exists());
+        assert!(memory.sidecar(LOG_EXTENSION, false).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_borrowed_page_access() {
+        const FILE_PATH: &str = "/tmp/test_borrowed_page_access.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.new_page().unwrap();
+        bpm.flush_all_page().unwrap();
+
+        bpm.write_at(1, 10, b"hello").unwrap();
+        assert_eq!(&*bpm.read_at(1, 10, 5).unwrap(), b"hello");
+        bpm.write_page(2).unwrap()[0] = 9;
+
+        // the bytes are borrowed from the frame, not copied
+        let page = bpm.read_page(2).unwrap();
+        let frame = bpm.fetch_page_read(2).unwrap();
+        assert_eq!(page.as_ptr(), frame.content.as_ptr());
+        assert_eq!(page[0], 9);
+        drop((page, frame));
+
+        let offset = FRAME_SIZE as usize - 2;
+        assert!(matches!(
+            bpm.write_at(1, offset, b"abc"),
+            Err(Error::OutOfPage {
+                page_id: 1,
+                len: 3,
+                ..
+            })
+        ));
+        assert!(bpm.read_at(1, offset, 3).is_err());
+
+        assert_eq!(bpm.flush_all_page().unwrap().pages_written, 2);
+        // reading does not dirty the frame
+        drop(bpm.read_page(1).unwrap());
+        drop(bpm.write_page(1).unwrap());
+        assert_eq!(bpm.flush_all_page().unwrap().pages_written, 0);
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let bpm = BufferPoolManager::open(2, FILE_PATH).unwrap();
+        assert_eq!(&*bpm.read_at(1, 10, 5).unwrap(), b"hello");
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_serialize_into_page() {
+        const FILE_PATH: &str = "/tmp/test_serialize_into_page.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        let entries: Vec<(u32, String)> = vec![(1, "one".into()), (2, "two".into())];
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+
+        let mut guard = bpm.fetch_page_write(1).unwrap();
+        bincode::serialize_into(&mut guard, &entries).unwrap();
+        // flushing the guard writes the page back through the pool
+        guard.flush().unwrap();
+        assert!(!guard.dirty);
+        assert!(!guard.flush_page().unwrap());
+        drop(guard);
+        assert_eq!(bpm.flush_all_page().unwrap().pages_written, 0);
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let bpm = BufferPoolManager::open(2, FILE_PATH).unwrap();
+        let mut guard = bpm.fetch_page_read(1).unwrap();
+        let decoded: Vec<(u32, String)> = bincode::deserialize_from(&mut guard).unwrap();
+        assert_eq!(decoded, entries);
+
+        // a write guard starts at the beginning of the page again
+        drop(guard);
+        let mut guard = bpm.fetch_page_write(1).unwrap();
+        let decoded: Vec<(u32, String)> = bincode::deserialize_from(&mut guard).unwrap();
+        assert_eq!(decoded, entries);
+        assert!(!guard.dirty);
+        drop(guard);
+
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_typed_pages() {
+        const FILE_PATH: &str = "/tmp/test_typed_pages.db";
+        let _ = fs::remove_file(FILE_PATH);
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Meta {
+            name: String,
+            root: u32,
+            children: Vec<u32>,
+        }
+        let meta = Meta {
+            name: "index".into(),
+            root: 3,
+            children: vec![4, 5],
+        };
+
+        let mut bpm = BufferPoolManager::new(2, FILE_PATH).unwrap();
+        bpm.new_page().unwrap();
+        bpm.write_typed(1, &meta).unwrap();
+        assert_eq!(bpm.read_typed::<Meta>(1).unwrap(), meta);
+        bpm.flush_all_page().unwrap();
+
+        // an encoding larger than the page is rejected, the page is untouched
+        let large = vec![1u8; FRAME_SIZE as usize];
+        assert!(matches!(
+            bpm.write_typed(1, &large),
+            Err(Error::OutOfPage { page_id: 1, .. })
+        ));
+        assert_eq!(bpm.flush_all_page().unwrap().pages_written, 0);
+        assert!(matches!(
+            bpm.read_typed::<u64>(2),
+            Err(Error::PageNotFound(2))
+        ));
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let bpm = BufferPoolManager::open(2, FILE_PATH).unwrap();
+        assert_eq!(bpm.read_typed::<Meta>(1).unwrap(), meta);
+        drop(bpm);
+        fs::remove_file(FILE_PATH).unwrap();
+    }
 }
@@ -1,3 +1,9 @@
+pub mod arena;
+pub mod async_manager;
 pub mod cache;
+pub mod guard;
 pub mod manager;
+pub mod recovery;
+pub mod replacer;
 pub mod scheduler;
+pub mod writer;
@@ -0,0 +1,544 @@
+// crash recovery, run when a database is opened with records
+// left in its log
+//
+// recovery follows ARIES in three passes over the log:
+//   analysis  finds the transactions that neither committed nor
+//             finished rolling back (the losers)
+//   redo      repeats history, every logged change is applied
+//             again unless the page LSN shows it already reached
+//             the disk. page directory changes are replayed too
+//   undo      rolls the losers back newest record first, writing
+//             a compensation record (CLR) for every undone change
+//             so a crash during undo never undoes a change twice
+//
+// the analysis starts at the checkpoint the master record in the
+// file header points to, its dirty page table tells how far back
+// the redo pass has to go
+//
+// once done every page is written back, the directory persisted
+// and the log reset, the same state a clean close leaves behind
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    error::{Error, Result},
+    storage::page::PageID,
+    wal::{record::LogBody, Lsn, TxnId, INVALID_LSN, INVALID_TXN},
+};
+
+use super::scheduler::{page_range, DiskManager};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecoveryReport {
+    // master record the analysis started at, `INVALID_LSN` when
+    // the whole log was read
+    pub checkpoint_lsn: Lsn,
+    // records read by the analysis
+    pub analyzed: usize,
+    // LSN the redo pass started at
+    pub redo_lsn: Lsn,
+    // page changes applied again during redo
+    pub redone: usize,
+    // page changes undone by rolling back the losers
+    pub undone: usize,
+    pub losers: Vec<TxnId>,
+}
+
+/// Brings the database back to the state of its committed
+/// transactions after a crash
+pub fn recover(disk_manager: &mut DiskManager) -> Result<RecoveryReport> {
+    let wal = disk_manager.wal();
+    // analysis starts at the last checkpoint, a master record left
+    // behind by a log that was reset since is ignored
+    let checkpoint_lsn = disk_manager.checkpoint_lsn();
+    let start = checkpoint_lsn.max(wal.base_lsn());
+    let records = wal.records_from(start)?;
+    let mut report = RecoveryReport {
+        checkpoint_lsn,
+        analyzed: records.len(),
+        ..RecoveryReport::default()
+    };
+
+    // analysis, last LSN of every transaction without an end and
+    // the recLSN of every page that may be missing changes
+    let mut transactions: HashMap<TxnId, Lsn> = HashMap::new();
+    let mut ended: HashSet<TxnId> = HashSet::new();
+    let mut dirty_pages: HashMap<PageID, Lsn> = HashMap::new();
+    for record in records.iter() {
+        match &record.body {
+            LogBody::Commit | LogBody::End => {
+                transactions.remove(&record.txn_id);
+                ended.insert(record.txn_id);
+                continue;
+            }
+            LogBody::PageWrite { page_id, .. } | LogBody::Compensation { page_id, .. } => {
+                dirty_pages.entry(*page_id).or_insert(record.lsn);
+            }
+            // the tables were taken at the begin record, what was
+            // seen for a page or transaction since is newer
+            LogBody::EndCheckpoint {
+                dirty_pages: checkpoint_pages,
+                transactions: checkpoint_transactions,
+            } => {
+                for (page_id, rec_lsn) in checkpoint_pages {
+                    let entry = dirty_pages.entry(*page_id).or_insert(*rec_lsn);
+                    *entry = (*entry).min(*rec_lsn);
+                }
+                for (txn_id, last_lsn) in checkpoint_transactions {
+                    if !ended.contains(txn_id) {
+                        transactions.entry(*txn_id).or_insert(*last_lsn);
+                    }
+                }
+            }
+            _ => {}
+        }
+        if record.txn_id != INVALID_TXN {
+            transactions.insert(record.txn_id, record.lsn);
+        }
+    }
+
+    // redo, starting at the oldest change that may be missing
+    report.redo_lsn = dirty_pages.values().copied().fold(start, Lsn::min);
+    let records = if report.redo_lsn < start {
+        wal.records_from(report.redo_lsn)?
+    } else {
+        records
+    };
+    let directory_lsn = disk_manager.directory_lsn();
+    for record in records {
+        match record.body {
+            LogBody::NewPage { page_id, offset } if record.lsn >= directory_lsn => {
+                disk_manager.redo_new_page(page_id, offset)?;
+            }
+            LogBody::DeletePage { page_id } if record.lsn >= directory_lsn => {
+                disk_manager.redo_delete_page(page_id)?;
+            }
+            // changes to pages deleted later on are lost with the page
+            LogBody::PageWrite {
+                page_id,
+                offset,
+                after,
+                ..
+            }
+            | LogBody::Compensation {
+                page_id,
+                offset,
+                after,
+                ..
+            } if dirty_pages
+                .get(&page_id)
+                .is_some_and(|rec_lsn| record.lsn >= *rec_lsn)
+                && disk_manager.contains_page(page_id) =>
+            {
+                let applied = apply(disk_manager, page_id, offset, &after, record.lsn, false)?;
+                report.redone += applied as usize;
+            }
+            _ => {}
+        }
+    }
+
+    // undo
+    report.losers = transactions.keys().copied().collect();
+    report.losers.sort();
+    report.undone = rollback(disk_manager, transactions.into_iter().collect())?;
+
+    disk_manager.write_back_all()?;
+    println!("[DEBUG][Recovery] {report:?}");
+
+    Ok(report)
+}
+
+/// Undoes the given transactions starting at their last LSN, the
+/// changes are undone across transactions newest first. Returns the
+/// number of page changes undone
+///
+/// frames touched by the transactions are latched, so guards on
+/// them have to be dropped before a rollback
+pub(crate) fn rollback(
+    disk_manager: &mut DiskManager,
+    transactions: Vec<(TxnId, Lsn)>,
+) -> Result<usize> {
+    let wal = disk_manager.wal();
+    // next LSN to undo for every transaction
+    let mut undo_next: HashMap<TxnId, Lsn> = HashMap::new();
+    for (txn_id, last_lsn) in transactions {
+        wal.resume(txn_id, last_lsn);
+        undo_next.insert(txn_id, last_lsn);
+    }
+
+    let mut undone = 0;
+    while let Some((&txn_id, &lsn)) = undo_next.iter().max_by_key(|(_, lsn)| **lsn) {
+        if lsn == INVALID_LSN {
+            wal.append(txn_id, LogBody::End)?;
+            undo_next.remove(&txn_id);
+            continue;
+        }
+
+        let record = wal.read_record(lsn)?;
+        let next = match record.body {
+            LogBody::PageWrite {
+                page_id,
+                offset,
+                before,
+                ..
+            } => {
+                if disk_manager.contains_page(page_id) {
+                    let clr = wal.append(
+                        txn_id,
+                        LogBody::Compensation {
+                            page_id,
+                            offset,
+                            after: before.clone(),
+                            undo_next_lsn: record.prev_lsn,
+                        },
+                    )?;
+                    apply(disk_manager, page_id, offset, &before, clr, true)?;
+                    undone += 1;
+                }
+                record.prev_lsn
+            }
+            // already undone before the crash
+            LogBody::Compensation { undo_next_lsn, .. } => undo_next_lsn,
+            _ => record.prev_lsn,
+        };
+        undo_next.insert(txn_id, next);
+    }
+
+    Ok(undone)
+}
+
+// writes `bytes` at `offset` of the page and moves its page LSN to
+// `lsn`. Unless forced the change is skipped when the page LSN shows
+// it is already applied, returns whether the page was changed
+fn apply(
+    disk_manager: &mut DiskManager,
+    page_id: PageID,
+    offset: usize,
+    bytes: &[u8],
+    lsn: Lsn,
+    force: bool,
+) -> Result<bool> {
+    // the log is read back from disk, a record reaching past the
+    // page is damaged and must not be applied
+    let range = page_range(page_id, offset, bytes.len())
+        .map_err(|e| Error::Corruption(format!("log record {lsn}: {e}")))?;
+
+    let (frame, pins) = disk_manager.pin_page(page_id)?;
+    // guards on the page are dropped before rolling back, waiting
+    // for the latch would hold the disk manager
+    let Some(mut frame) = frame.try_write() else {
+        pins.unpin();
+        return Err(Error::PagePinned(page_id));
+    };
+
+    let applied = force || frame.page_lsn < lsn;
+    if applied {
+        frame.content[range].copy_from_slice(bytes);
+        frame.page_lsn = lsn;
+        frame.mark_dirty();
+    }
+
+    drop(frame);
+    pins.unpin();
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env, fs,
+        process::{Command, Stdio},
+        thread,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use crate::{
+        buffer::manager::BufferPoolManager,
+        error::Error,
+        storage::page::FRAME_SIZE,
+        wal::{record::LogBody, LogManager},
+    };
+
+    fn read_value(bpm: &BufferPoolManager, page_id: u32) -> u64 {
+        let guard = bpm.fetch_page_read(page_id).unwrap();
+        u64::from_le_bytes(guard.content[..8].try_into().unwrap())
+    }
+
+    fn write_value(bpm: &BufferPoolManager, txn_id: u64, page_id: u32, value: u64) {
+        let mut guard = bpm.fetch_page_write(page_id).unwrap();
+        guard.write_logged(txn_id, 0, &value.to_le_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_recovery_redo_and_undo() {
+        const DB_PATH: &str = "/tmp/test_recovery_redo_and_undo.db";
+        let _ = fs::remove_file(DB_PATH);
+
+        // pages created in the crashed session only exist in the log
+        let mut bpm = BufferPoolManager::new(2, DB_PATH).unwrap();
+        for _ in 0..4 {
+            bpm.new_page().unwrap();
+        }
+
+        let winner = bpm.begin().unwrap();
+        write_value(&bpm, winner, 1, 11);
+        bpm.commit(winner).unwrap();
+
+        // the small pool writes uncommitted changes to disk
+        let loser = bpm.begin().unwrap();
+        for page_id in 2..=4 {
+            write_value(&bpm, loser, page_id, 22);
+        }
+
+        let aborted = bpm.begin().unwrap();
+        write_value(&bpm, aborted, 1, 33);
+        bpm.abort(aborted).unwrap();
+        assert_eq!(read_value(&bpm, 1), 11);
+
+        // crash, nothing is written back
+        std::mem::forget(bpm);
+
+        let mut bpm = BufferPoolManager::open(2, DB_PATH).unwrap();
+        assert_eq!(read_value(&bpm, 1), 11);
+        for page_id in 2..=4 {
+            assert_eq!(read_value(&bpm, page_id), 0);
+        }
+        let wal = bpm.disk_manager.lock().unwrap().wal();
+        assert!(wal.records().unwrap().is_empty());
+
+        bpm.close().unwrap();
+        drop(bpm);
+        fs::remove_file(DB_PATH).unwrap();
+        fs::remove_file(LogManager::log_path(DB_PATH)).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_bounds_recovery() {
+        const DB_PATH: &str = "/tmp/test_checkpoint_bounds_recovery.db";
+        let _ = fs::remove_file(DB_PATH);
+
+        let mut bpm = BufferPoolManager::new(4, DB_PATH).unwrap();
+        for _ in 0..4 {
+            bpm.new_page().unwrap();
+        }
+        let wal = bpm.disk_manager.lock().unwrap().wal();
+
+        let before = bpm.begin().unwrap();
+        write_value(&bpm, before, 1, 11);
+        write_value(&bpm, before, 2, 11);
+        bpm.commit(before).unwrap();
+        bpm.flush_all_page().unwrap();
+
+        // running across the checkpoint, never commits
+        let loser = bpm.begin().unwrap();
+        write_value(&bpm, loser, 3, 22);
+
+        // a writer holding its latch does not block the checkpoint
+        let during = bpm.begin().unwrap();
+        let mut guard = bpm.fetch_page_write(4).unwrap();
+        guard.write_logged(during, 0, &33u64.to_le_bytes()).unwrap();
+        let checkpoint = bpm.checkpoint().unwrap();
+        drop(guard);
+        bpm.commit(during).unwrap();
+
+        let after = bpm.begin().unwrap();
+        write_value(&bpm, after, 1, 44);
+        bpm.commit(after).unwrap();
+
+        let total = wal.records().unwrap().len();
+        std::mem::forget(bpm);
+
+        let mut bpm = BufferPoolManager::open(4, DB_PATH).unwrap();
+        let report = bpm
+            .disk_manager
+            .lock()
+            .unwrap()
+            .recovery_report()
+            .cloned()
+            .unwrap();
+        assert_eq!(report.checkpoint_lsn, checkpoint);
+        assert!(report.analyzed < total);
+        // pages 3 and 4 were dirty at the checkpoint
+        assert!(report.redo_lsn < checkpoint);
+        assert_eq!(report.losers.len(), 1);
+
+        let values: Vec<u64> = (1..=4).map(|page_id| read_value(&bpm, page_id)).collect();
+        assert_eq!(values, vec![44, 11, 0, 33]);
+
+        bpm.close().unwrap();
+        drop(bpm);
+        fs::remove_file(DB_PATH).unwrap();
+        fs::remove_file(LogManager::log_path(DB_PATH)).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_record_is_corruption() {
+        const DB_PATH: &str = "/tmp/test_damaged_record_is_corruption.db";
+        let _ = fs::remove_file(DB_PATH);
+
+        let mut bpm = BufferPoolManager::new(2, DB_PATH).unwrap();
+        bpm.new_page().unwrap();
+        let wal = bpm.disk_manager.lock().unwrap().wal();
+        let txn_id = bpm.begin().unwrap();
+        let lsn = wal
+            .append(
+                txn_id,
+                LogBody::PageWrite {
+                    page_id: 1,
+                    offset: FRAME_SIZE as usize - 4,
+                    before: vec![0; 8],
+                    after: vec![1; 8],
+                },
+            )
+            .unwrap();
+        wal.flush(lsn).unwrap();
+        std::mem::forget(bpm);
+
+        // the record reaches past the page, redo refuses to apply it
+        let result = BufferPoolManager::open(2, DB_PATH);
+        assert!(matches!(result, Err(Error::Corruption(_))));
+
+        drop(wal);
+        fs::remove_file(DB_PATH).unwrap();
+        fs::remove_file(LogManager::log_path(DB_PATH)).unwrap();
+    }
+
+    const CRASH_CHILD_ENV: &str = "FORKLIFT_CRASH_CHILD";
+    const CRASH_PAGES: u32 = 8;
+
+    // every transaction writes its number into two pages, every
+    // fourth one is rolled back
+    fn crash_txn_pages(txn: u64) -> [u32; 2] {
+        [
+            (txn % CRASH_PAGES as u64) as u32 + 1,
+            ((txn * 7 + 3) % CRASH_PAGES as u64) as u32 + 1,
+        ]
+    }
+
+    fn crash_txn_aborts(txn: u64) -> bool {
+        txn % 4 == 3
+    }
+
+    // page values once the first `committed` transactions ran
+    fn crash_expected(committed: u64) -> Vec<u64> {
+        let mut values = vec![0; CRASH_PAGES as usize];
+        for txn in (1..=committed).filter(|txn| !crash_txn_aborts(*txn)) {
+            for page_id in crash_txn_pages(txn) {
+                values[page_id as usize - 1] = txn;
+            }
+        }
+        values
+    }
+
+    fn progress_path(db_path: &str) -> String {
+        format!("{db_path}.progress")
+    }
+
+    fn read_progress(db_path: &str) -> u64 {
+        fs::read_to_string(progress_path(db_path))
+            .map(|progress| progress.parse().unwrap())
+            .unwrap_or(0)
+    }
+
+    fn write_progress(db_path: &str, committed: u64) {
+        // renamed into place, a crash never leaves half a number
+        let tmp = format!("{db_path}.progress.tmp");
+        fs::write(&tmp, committed.to_string()).unwrap();
+        fs::rename(tmp, progress_path(db_path)).unwrap();
+    }
+
+    // workload run in a separate process by `test_crash_recovery`,
+    // it keeps committing transactions, checkpointing now and then,
+    // until it is killed
+    #[test]
+    #[ignore]
+    fn crash_child() {
+        let Ok(db_path) = env::var(CRASH_CHILD_ENV) else {
+            return;
+        };
+
+        let bpm = BufferPoolManager::open(3, &db_path).unwrap();
+        for txn in read_progress(&db_path) + 1.. {
+            let txn_id = bpm.begin().unwrap();
+            for page_id in crash_txn_pages(txn) {
+                write_value(&bpm, txn_id, page_id, txn);
+            }
+
+            if crash_txn_aborts(txn) {
+                bpm.abort(txn_id).unwrap();
+            } else {
+                bpm.commit(txn_id).unwrap();
+            }
+            write_progress(&db_path, txn);
+
+            if txn % 16 == 0 {
+                bpm.checkpoint().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_crash_recovery() {
+        const DB_PATH: &str = "/tmp/test_crash_recovery.db";
+        let _ = fs::remove_file(DB_PATH);
+        let _ = fs::remove_file(progress_path(DB_PATH));
+
+        let mut bpm = BufferPoolManager::new(3, DB_PATH).unwrap();
+        for _ in 0..CRASH_PAGES {
+            bpm.new_page().unwrap();
+        }
+        bpm.close().unwrap();
+        drop(bpm);
+
+        let mut seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+            | 1;
+        for _ in 0..4 {
+            let started = read_progress(DB_PATH);
+            let mut child = Command::new(env::current_exe().unwrap())
+                .args([
+                    "--exact",
+                    "buffer::recovery::test::crash_child",
+                    "--ignored",
+                    "--nocapture",
+                ])
+                .env(CRASH_CHILD_ENV, DB_PATH)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap();
+
+            // let the child get going, then kill it at a random point
+            while read_progress(DB_PATH) == started {
+                assert!(child.try_wait().unwrap().is_none(), "crash child failed");
+                thread::sleep(Duration::from_millis(1));
+            }
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            thread::sleep(Duration::from_millis(seed % 100));
+            child.kill().unwrap();
+            child.wait().unwrap();
+
+            // the transaction after the last recorded one may have
+            // committed right before the crash
+            let committed = read_progress(DB_PATH);
+            let mut bpm = BufferPoolManager::open(3, DB_PATH).unwrap();
+            let values: Vec<u64> = (1..=CRASH_PAGES)
+                .map(|page_id| read_value(&bpm, page_id))
+                .collect();
+            if values == crash_expected(committed + 1) {
+                write_progress(DB_PATH, committed + 1);
+            } else {
+                assert_eq!(values, crash_expected(committed));
+            }
+            bpm.close().unwrap();
+        }
+
+        fs::remove_file(DB_PATH).unwrap();
+        fs::remove_file(LogManager::log_path(DB_PATH)).unwrap();
+        fs::remove_file(progress_path(DB_PATH)).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::storage::page::PageID;
+
+use super::{list::PageList, Replacer};
+
+/// Adaptive Replacement Cache (Megiddo, Modha 2003). Resident pages
+/// live in `t1` (seen once recently) or `t2` (seen at least twice),
+/// evicted pages are remembered in the ghost lists `b1` and `b2`.
+/// A hit in a ghost list shows which of the two lists was evicted
+/// from too eagerly and moves the target size `p` of `t1` towards it,
+/// so a one-time scan only ever churns `t1`
+#[derive(Debug)]
+pub struct ArcReplacer {
+    capacity: usize,
+    // target size of t1
+    p: usize,
+
+    t1: PageList,
+    t2: PageList,
+    b1: PageList,
+    b2: PageList,
+
+    // evictable flag of every resident page
+    evictable: HashMap<PageID, bool>,
+    evictable_count: usize,
+}
+
+impl ArcReplacer {
+    pub fn new(capacity: usize) -> ArcReplacer {
+        assert!(capacity > 0, "ARC needs room for at least one frame");
+
+        ArcReplacer {
+            capacity,
+            p: 0,
+            t1: PageList::new(),
+            t2: PageList::new(),
+            b1: PageList::new(),
+            b2: PageList::new(),
+            evictable: HashMap::new(),
+            evictable_count: 0,
+        }
+    }
+
+    fn evict_from(&mut self, from_t1: bool) -> Option<PageID> {
+        let evictable = &self.evictable;
+        let (list, ghosts) = if from_t1 {
+            (&mut self.t1, &mut self.b1)
+        } else {
+            (&mut self.t2, &mut self.b2)
+        };
+
+        let victim = list.find_oldest(|page_id| evictable[&page_id])?;
+        list.remove(victim);
+        ghosts.push_back(victim);
+
+        self.evictable.remove(&victim);
+        self.evictable_count -= 1;
+        self.trim_ghosts();
+
+        Some(victim)
+    }
+
+    fn prefer_t1(&self) -> bool {
+        !self.t1.is_empty() && self.t1.len() >= self.p.max(1)
+    }
+
+    // |t1| + |b1| <= c and the whole directory <= 2c
+    fn trim_ghosts(&mut self) {
+        while self.t1.len() + self.b1.len() > self.capacity && !self.b1.is_empty() {
+            self.b1.pop_front();
+        }
+        while self.t1.len() + self.t2.len() + self.b1.len() + self.b2.len() > 2 * self.capacity
+            && !self.b2.is_empty()
+        {
+            self.b2.pop_front();
+        }
+    }
+}
+
+impl Replacer for ArcReplacer {
+    fn record_access(&mut self, page_id: PageID) {
+        if self.t1.remove(page_id) || self.t2.contains(page_id) {
+            self.t2.push_back(page_id);
+            return;
+        }
+
+        if self.b1.remove(page_id) {
+            // t1 was too small
+            let delta = (self.b2.len() / self.b1.len().max(1)).max(1);
+            self.p = (self.p + delta).min(self.capacity);
+            self.t2.push_back(page_id);
+        } else if self.b2.remove(page_id) {
+            // t2 was too small
+            let delta = (self.b1.len() / self.b2.len().max(1)).max(1);
+            self.p = self.p.saturating_sub(delta);
+            self.t2.push_back(page_id);
+        } else {
+            self.t1.push_back(page_id);
+            self.trim_ghosts();
+        }
+
+        self.evictable.insert(page_id, false);
+    }
+
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool) {
+        if let Some(current) = self.evictable.get_mut(&page_id) {
+            if *current != evictable {
+                *current = evictable;
+                if evictable {
+                    self.evictable_count += 1;
+                } else {
+                    self.evictable_count -= 1;
+                }
+            }
+        }
+    }
+
+    fn evict(&mut self) -> Option<PageID> {
+        if self.evictable_count == 0 {
+            return None;
+        }
+
+        let prefer_t1 = self.prefer_t1();
+        self.evict_from(prefer_t1)
+            .or_else(|| self.evict_from(!prefer_t1))
+    }
+
+    fn restore(&mut self, page_id: PageID, _referenced: &Arc<AtomicBool>) {
+        if self.evictable.contains_key(&page_id) {
+            return;
+        }
+
+        // back to the front of the list it was evicted from,
+        // leaving `p` alone since this is no ghost hit
+        if self.b2.remove(page_id) {
+            self.t2.push_front(page_id);
+        } else {
+            self.b1.remove(page_id);
+            self.t1.push_front(page_id);
+            self.trim_ghosts();
+        }
+        self.evictable.insert(page_id, false);
+    }
+
+    fn remove(&mut self, page_id: PageID) {
+        if let Some(evictable) = self.evictable.remove(&page_id) {
+            if evictable {
+                self.evictable_count -= 1;
+            }
+        }
+        self.t1.remove(page_id);
+        self.t2.remove(page_id);
+        self.b1.remove(page_id);
+        self.b2.remove(page_id);
+    }
+
+    fn candidates(&self, count: usize) -> Vec<PageID> {
+        let (first, second) = if self.prefer_t1() {
+            (&self.t1, &self.t2)
+        } else {
+            (&self.t2, &self.t1)
+        };
+
+        first
+            .iter()
+            .chain(second.iter())
+            .filter(|page_id| self.evictable[page_id])
+            .take(count)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.evictable_count
+    }
+}
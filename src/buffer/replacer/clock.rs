@@ -0,0 +1,262 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::storage::page::PageID;
+
+use super::Replacer;
+
+// marks the hand of an empty ring
+const NIL: usize = usize::MAX;
+
+#[derive(Debug)]
+struct ClockNode {
+    page_id: PageID,
+    // shared with the cache, a reader pinning the page sets
+    // it without going through the replacer
+    referenced: Arc<AtomicBool>,
+    evictable: bool,
+    prev: usize,
+    next: usize,
+}
+
+/// CLOCK approximates LRU with a reference bit per page. An access
+/// only sets the bit, the hand sweeping the ring clears it and evicts
+/// the first evictable page whose bit is already clear
+///
+/// the ring is a circular list of nodes living in a slab and linked
+/// by index, so pages are added and removed in O(1). the reference
+/// bits are atomics handed to the cache, see `Replacer::track`
+#[derive(Debug)]
+pub struct ClockReplacer {
+    nodes: Vec<ClockNode>,
+    free: Vec<usize>,
+    positions: HashMap<PageID, usize>,
+    // node the hand points at, the next one looked at
+    hand: usize,
+    evictable: usize,
+}
+
+impl Default for ClockReplacer {
+    fn default() -> ClockReplacer {
+        ClockReplacer {
+            nodes: vec![],
+            free: vec![],
+            positions: HashMap::new(),
+            hand: NIL,
+            evictable: 0,
+        }
+    }
+}
+
+impl ClockReplacer {
+    pub fn new() -> ClockReplacer {
+        ClockReplacer::default()
+    }
+
+    // new pages go right behind the hand, so they are the
+    // last ones it reaches
+    fn insert(&mut self, page_id: PageID, referenced: Arc<AtomicBool>) {
+        let mut node = ClockNode {
+            page_id,
+            referenced,
+            evictable: false,
+            prev: NIL,
+            next: NIL,
+        };
+        let idx = self.free.pop().unwrap_or(self.nodes.len());
+        if self.hand == NIL {
+            (node.prev, node.next) = (idx, idx);
+            self.hand = idx;
+        } else {
+            (node.prev, node.next) = (self.nodes[self.hand].prev, self.hand);
+        }
+
+        let (prev, next) = (node.prev, node.next);
+        if idx == self.nodes.len() {
+            self.nodes.push(node);
+        } else {
+            self.nodes[idx] = node;
+        }
+        self.nodes[prev].next = idx;
+        self.nodes[next].prev = idx;
+        self.positions.insert(page_id, idx);
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = (self.nodes[idx].prev, self.nodes[idx].next);
+        if next == idx {
+            self.hand = NIL;
+        } else {
+            self.nodes[prev].next = next;
+            self.nodes[next].prev = prev;
+            if self.hand == idx {
+                self.hand = next;
+            }
+        }
+        self.free.push(idx);
+    }
+}
+
+impl Replacer for ClockReplacer {
+    fn record_access(&mut self, page_id: PageID) {
+        match self.positions.get(&page_id) {
+            Some(idx) => self.nodes[*idx].referenced.store(true, Ordering::Relaxed),
+            None => self.insert(page_id, Arc::new(AtomicBool::new(true))),
+        }
+    }
+
+    fn uses_reference_bits(&self) -> bool {
+        true
+    }
+
+    fn track(&mut self, page_id: PageID, referenced: &Arc<AtomicBool>) {
+        referenced.store(true, Ordering::Relaxed);
+        match self.positions.get(&page_id) {
+            Some(idx) => self.nodes[*idx].referenced = Arc::clone(referenced),
+            None => self.insert(page_id, Arc::clone(referenced)),
+        }
+    }
+
+    fn restore(&mut self, page_id: PageID, referenced: &Arc<AtomicBool>) {
+        // unlinking moved the hand on to the next node, the page
+        // goes back right behind it and the hand back onto it
+        if !self.positions.contains_key(&page_id) {
+            self.insert(page_id, Arc::clone(referenced));
+            self.hand = self.positions[&page_id];
+        }
+    }
+
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool) {
+        if let Some(idx) = self.positions.get(&page_id) {
+            let node = &mut self.nodes[*idx];
+            if node.evictable != evictable {
+                node.evictable = evictable;
+                if evictable {
+                    self.evictable += 1;
+                } else {
+                    self.evictable -= 1;
+                }
+            }
+        }
+    }
+
+    fn evict(&mut self) -> Option<PageID> {
+        if self.evictable == 0 {
+            return None;
+        }
+
+        // the first sweep clears every reference bit it passes, so a
+        // victim is found within two turns of the hand
+        for _ in 0..2 * self.positions.len() + 1 {
+            let node = &self.nodes[self.hand];
+            if node.evictable && !node.referenced.swap(false, Ordering::Relaxed) {
+                let page_id = node.page_id;
+                self.remove(page_id);
+                return Some(page_id);
+            }
+
+            self.hand = node.next;
+        }
+
+        None
+    }
+
+    fn remove(&mut self, page_id: PageID) {
+        if let Some(idx) = self.positions.remove(&page_id) {
+            if self.nodes[idx].evictable {
+                self.evictable -= 1;
+            }
+            self.unlink(idx);
+        }
+    }
+
+    fn candidates(&self, count: usize) -> Vec<PageID> {
+        // in the order the hand reaches them, pages whose bit is
+        // already clear go before the ones given a second chance
+        let mut idx = self.hand;
+        let (mut cold, hot): (Vec<_>, Vec<_>) = (0..self.positions.len())
+            .map(|_| {
+                let node = &self.nodes[idx];
+                idx = node.next;
+                node
+            })
+            .filter(|node| node.evictable)
+            .partition(|node| !node.referenced.load(Ordering::Relaxed));
+
+        cold.extend(hot);
+        cold.into_iter()
+            .take(count)
+            .map(|node| node.page_id)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.evictable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::buffer::replacer::Replacer;
+
+    use super::ClockReplacer;
+
+    fn access(replacer: &mut dyn Replacer, page_id: u32) {
+        replacer.record_access(page_id);
+        replacer.set_evictable(page_id, true);
+    }
+
+    #[test]
+    fn test_clock_second_chance() {
+        let mut replacer = ClockReplacer::new();
+        for page_id in [1, 2, 3] {
+            access(&mut replacer, page_id);
+        }
+
+        // first sweep clears every bit, 1 is reached first afterwards
+        assert_eq!(replacer.evict(), Some(1));
+
+        // 2 is referenced again and survives the next sweep
+        access(&mut replacer, 2);
+        assert_eq!(replacer.evict(), Some(3));
+
+        replacer.set_evictable(2, false);
+        assert_eq!(replacer.evict(), None);
+        replacer.set_evictable(2, true);
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.size(), 0);
+    }
+
+    #[test]
+    fn test_clock_reads_shared_reference_bits() {
+        let mut replacer = ClockReplacer::new();
+        let bits: Vec<_> = (0..3).map(|_| Arc::new(AtomicBool::new(false))).collect();
+        for (page_id, bit) in (1..=3).zip(&bits) {
+            replacer.track(page_id, bit);
+            replacer.set_evictable(page_id, true);
+        }
+        assert_eq!(replacer.evict(), Some(1));
+
+        // an access recorded straight in the bit gives 2 a second chance
+        bits[1].store(true, Ordering::Relaxed);
+        assert_eq!(replacer.evict(), Some(3));
+
+        // removed nodes leave free slots behind, the slab does not grow
+        for page_id in 10..100 {
+            access(&mut replacer, page_id);
+            replacer.remove(page_id - 1);
+        }
+        assert!(replacer.nodes.len() <= 3);
+        assert_eq!(replacer.candidates(4), [2, 99]);
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::storage::page::PageID;
+
+use super::Replacer;
+
+// marks the hands of an empty ring
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Hot,
+    Cold,
+    // evicted cold page still in its test period, only
+    // its page id is remembered
+    NonResident,
+}
+
+#[derive(Debug)]
+struct ClockProNode {
+    page_id: PageID,
+    status: Status,
+    // shared with the cache, a reader pinning the page sets
+    // it without going through the replacer
+    referenced: Arc<AtomicBool>,
+    // a cold page in its test period is promoted to hot
+    // if it is accessed again before the period ends
+    in_test: bool,
+    evictable: bool,
+    prev: usize,
+    next: usize,
+}
+
+/// CLOCK-Pro (Jiang, Chen, Zhang 2005) splits resident pages into hot
+/// and cold ones and only evicts cold pages. A newly added cold page
+/// gets a test period, if it is re-accessed within it (even after being
+/// evicted) it is promoted to hot. Three hands share a single ring:
+///
+/// - `hand_cold` evicts cold pages and promotes referenced test pages
+/// - `hand_hot` demotes unreferenced hot pages to keep `hot` below
+///   `capacity - cold_target` and ends test periods it passes
+/// - `hand_test` drops non-resident pages once there are more than
+///   `capacity` of them
+///
+/// `cold_target` adapts: it grows when a non-resident page is accessed
+/// again and shrinks when a test period expires without a re-access
+///
+/// like `ClockReplacer` the ring is a circular list of nodes living in
+/// a slab and linked by index, and the reference bits are atomics
+/// handed to the cache, see `Replacer::track`
+#[derive(Debug)]
+pub struct ClockProReplacer {
+    capacity: usize,
+    cold_target: usize,
+
+    nodes: Vec<ClockProNode>,
+    free: Vec<usize>,
+    positions: HashMap<PageID, usize>,
+    // hands follow `next`, new pages are inserted right
+    // behind `hand_hot`
+    hand_hot: usize,
+    hand_cold: usize,
+    hand_test: usize,
+
+    hot: usize,
+    non_resident: usize,
+    evictable: usize,
+}
+
+impl ClockProReplacer {
+    /// `capacity` is the number of resident frames, the same number
+    /// of non-resident pages is remembered
+    pub fn new(capacity: usize) -> ClockProReplacer {
+        assert!(capacity > 0, "CLOCK-Pro needs room for at least one frame");
+
+        ClockProReplacer {
+            capacity,
+            cold_target: 1,
+            nodes: vec![],
+            free: vec![],
+            positions: HashMap::new(),
+            hand_hot: NIL,
+            hand_cold: NIL,
+            hand_test: NIL,
+            hot: 0,
+            non_resident: 0,
+            evictable: 0,
+        }
+    }
+
+    fn insert(
+        &mut self,
+        page_id: PageID,
+        status: Status,
+        in_test: bool,
+        referenced: Arc<AtomicBool>,
+    ) -> usize {
+        self.insert_at(self.hand_hot, page_id, status, in_test, referenced)
+    }
+
+    // links a new node right behind the node at `before`
+    fn insert_at(
+        &mut self,
+        before: usize,
+        page_id: PageID,
+        status: Status,
+        in_test: bool,
+        referenced: Arc<AtomicBool>,
+    ) -> usize {
+        let mut node = ClockProNode {
+            page_id,
+            status,
+            referenced,
+            in_test,
+            evictable: false,
+            prev: NIL,
+            next: NIL,
+        };
+        let idx = self.free.pop().unwrap_or(self.nodes.len());
+        if before == NIL {
+            (node.prev, node.next) = (idx, idx);
+            (self.hand_hot, self.hand_cold, self.hand_test) = (idx, idx, idx);
+        } else {
+            (node.prev, node.next) = (self.nodes[before].prev, before);
+        }
+
+        let (prev, next) = (node.prev, node.next);
+        if idx == self.nodes.len() {
+            self.nodes.push(node);
+        } else {
+            self.nodes[idx] = node;
+        }
+        self.nodes[prev].next = idx;
+        self.nodes[next].prev = idx;
+        self.positions.insert(page_id, idx);
+        idx
+    }
+
+    // hands pointing at the node move on to the next one
+    fn unlink(&mut self, idx: usize) {
+        let ClockProNode {
+            page_id,
+            prev,
+            next,
+            ..
+        } = self.nodes[idx];
+        self.positions.remove(&page_id);
+
+        if next == idx {
+            (self.hand_hot, self.hand_cold, self.hand_test) = (NIL, NIL, NIL);
+        } else {
+            self.nodes[prev].next = next;
+            self.nodes[next].prev = prev;
+            for hand in [&mut self.hand_hot, &mut self.hand_cold, &mut self.hand_test] {
+                if *hand == idx {
+                    *hand = next;
+                }
+            }
+        }
+        self.free.push(idx);
+    }
+
+    fn max_hot(&self) -> usize {
+        self.capacity.saturating_sub(self.cold_target).max(1)
+    }
+
+    /// Demotes hot pages until there are at most `max_hot` of them.
+    /// With `force` a full turn is made regardless, used when every
+    /// evictable page is hot
+    fn run_hand_hot(&mut self, force: bool) {
+        let mut steps = 2 * self.positions.len();
+
+        while steps > 0 && self.hand_hot != NIL && (force || self.hot > self.max_hot()) {
+            steps -= 1;
+            let idx = self.hand_hot;
+            let node = &mut self.nodes[idx];
+
+            match node.status {
+                Status::Hot => {
+                    if !node.referenced.swap(false, Ordering::Relaxed) {
+                        node.status = Status::Cold;
+                        node.in_test = false;
+                        self.hot -= 1;
+                    }
+                }
+                Status::Cold => {
+                    if !node.referenced.load(Ordering::Relaxed) {
+                        node.in_test = false;
+                    }
+                }
+                Status::NonResident => {
+                    // test period ran out without a re-access
+                    self.non_resident -= 1;
+                    self.cold_target = self.cold_target.saturating_sub(1).max(1);
+                    self.unlink(idx);
+                    continue;
+                }
+            }
+
+            self.hand_hot = self.nodes[idx].next;
+        }
+    }
+
+    /// Drops non-resident pages until at most `capacity` are kept
+    fn run_hand_test(&mut self) {
+        let mut steps = 2 * self.positions.len();
+
+        while steps > 0 && self.non_resident > self.capacity {
+            steps -= 1;
+            let idx = self.hand_test;
+            let node = &mut self.nodes[idx];
+
+            match node.status {
+                Status::Cold => node.in_test = false,
+                Status::NonResident => {
+                    self.non_resident -= 1;
+                    self.cold_target = self.cold_target.saturating_sub(1).max(1);
+                    self.unlink(idx);
+                    continue;
+                }
+                Status::Hot => {}
+            }
+
+            self.hand_test = self.nodes[idx].next;
+        }
+    }
+
+    /// Sweeps `hand_cold` once around the ring looking for an
+    /// evictable, unreferenced cold page
+    fn run_hand_cold(&mut self) -> Option<PageID> {
+        let mut steps = 2 * self.positions.len();
+
+        while steps > 0 && self.hand_cold != NIL {
+            steps -= 1;
+            let idx = self.hand_cold;
+            let node = &mut self.nodes[idx];
+            let page_id = node.page_id;
+
+            if node.status != Status::Cold || !node.evictable {
+                self.hand_cold = node.next;
+                continue;
+            }
+
+            if node.referenced.swap(false, Ordering::Relaxed) {
+                if node.in_test {
+                    node.status = Status::Hot;
+                    node.in_test = false;
+                    self.hot += 1;
+                    self.hand_cold = node.next;
+                    self.run_hand_hot(false);
+                } else {
+                    // start a new test period from the head of the ring
+                    let referenced = Arc::clone(&node.referenced);
+                    self.unlink(idx);
+                    let idx = self.insert(page_id, Status::Cold, true, referenced);
+                    self.nodes[idx].evictable = true;
+                }
+                continue;
+            }
+
+            self.evictable -= 1;
+            if node.in_test {
+                node.status = Status::NonResident;
+                node.evictable = false;
+                self.non_resident += 1;
+                self.hand_cold = node.next;
+                self.run_hand_test();
+            } else {
+                self.unlink(idx);
+            }
+
+            return Some(page_id);
+        }
+
+        None
+    }
+}
+
+impl Replacer for ClockProReplacer {
+    fn record_access(&mut self, page_id: PageID) {
+        match self.positions.get(&page_id) {
+            Some(idx) if self.nodes[*idx].status != Status::NonResident => {
+                self.nodes[*idx].referenced.store(true, Ordering::Relaxed);
+            }
+            _ => self.track(page_id, &Arc::default()),
+        }
+    }
+
+    fn uses_reference_bits(&self) -> bool {
+        true
+    }
+
+    fn track(&mut self, page_id: PageID, referenced: &Arc<AtomicBool>) {
+        match self.positions.get(&page_id).copied() {
+            Some(idx) if self.nodes[idx].status != Status::NonResident => {
+                referenced.store(true, Ordering::Relaxed);
+                self.nodes[idx].referenced = Arc::clone(referenced);
+            }
+            Some(idx) => {
+                // re-accessed during its test period, the page was
+                // evicted too early so cold pages get more room
+                self.unlink(idx);
+                self.non_resident -= 1;
+                self.cold_target = (self.cold_target + 1).min(self.capacity);
+
+                self.insert(page_id, Status::Hot, false, Arc::clone(referenced));
+                self.hot += 1;
+                self.run_hand_hot(false);
+            }
+            None => {
+                self.insert(page_id, Status::Cold, true, Arc::clone(referenced));
+            }
+        }
+    }
+
+    fn restore(&mut self, page_id: PageID, referenced: &Arc<AtomicBool>) {
+        let idx = match self.positions.get(&page_id).copied() {
+            Some(idx) if self.nodes[idx].status == Status::NonResident => {
+                // still in its test period, the node never left the ring
+                let node = &mut self.nodes[idx];
+                node.status = Status::Cold;
+                node.referenced = Arc::clone(referenced);
+                self.non_resident -= 1;
+                idx
+            }
+            Some(_) => return,
+            // unlinking moved the cold hand on to the next node
+            None => self.insert_at(
+                self.hand_cold,
+                page_id,
+                Status::Cold,
+                false,
+                Arc::clone(referenced),
+            ),
+        };
+        self.hand_cold = idx;
+    }
+
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool) {
+        if let Some(idx) = self.positions.get(&page_id) {
+            let node = &mut self.nodes[*idx];
+            if node.status != Status::NonResident && node.evictable != evictable {
+                node.evictable = evictable;
+                if evictable {
+                    self.evictable += 1;
+                } else {
+                    self.evictable -= 1;
+                }
+            }
+        }
+    }
+
+    fn evict(&mut self) -> Option<PageID> {
+        if self.evictable == 0 {
+            return None;
+        }
+
+        if let Some(page_id) = self.run_hand_cold() {
+            return Some(page_id);
+        }
+
+        // every evictable page is hot, demote them and retry
+        self.run_hand_hot(true);
+        self.run_hand_cold()
+    }
+
+    fn remove(&mut self, page_id: PageID) {
+        let Some(&idx) = self.positions.get(&page_id) else {
+            return;
+        };
+
+        let node = &self.nodes[idx];
+        match node.status {
+            Status::Hot => self.hot -= 1,
+            Status::NonResident => self.non_resident -= 1,
+            Status::Cold => {}
+        }
+        if node.evictable {
+            self.evictable -= 1;
+        }
+        self.unlink(idx);
+    }
+
+    fn candidates(&self, count: usize) -> Vec<PageID> {
+        // unreferenced cold pages from `hand_cold` on, referenced ones
+        // get another test period first and hot pages only get
+        // evicted once they are demoted
+        let mut idx = self.hand_cold;
+        let mut pages: Vec<&ClockProNode> = (0..self.positions.len())
+            .map(|_| {
+                let node = &self.nodes[idx];
+                idx = node.next;
+                node
+            })
+            .filter(|node| node.status != Status::NonResident && node.evictable)
+            .collect();
+        pages.sort_by_key(|node| {
+            (
+                node.status == Status::Hot,
+                node.referenced.load(Ordering::Relaxed),
+            )
+        });
+
+        pages
+            .into_iter()
+            .take(count)
+            .map(|node| node.page_id)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.evictable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::buffer::replacer::Replacer;
+
+    use super::ClockProReplacer;
+
+    fn access(replacer: &mut dyn Replacer, page_id: u32) {
+        replacer.record_access(page_id);
+        replacer.set_evictable(page_id, true);
+    }
+
+    #[test]
+    fn test_clock_pro_promotes_reaccessed_pages() {
+        let mut replacer = ClockProReplacer::new(3);
+        for page_id in [1, 2, 3] {
+            access(&mut replacer, page_id);
+        }
+
+        // page 1 is re-accessed while in its test period
+        access(&mut replacer, 1);
+        let victim = replacer.evict().unwrap();
+        assert_ne!(victim, 1);
+
+        // the victim is remembered as non-resident, faulting it back
+        // in promotes it straight to hot
+        access(&mut replacer, victim);
+        for _ in 0..2 {
+            let next = replacer.evict().unwrap();
+            assert_ne!(next, victim);
+        }
+        assert_eq!(replacer.size(), 1);
+    }
+
+    #[test]
+    fn test_clock_pro_never_evicts_pinned() {
+        let mut replacer = ClockProReplacer::new(2);
+        replacer.record_access(1);
+        access(&mut replacer, 2);
+
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), None);
+
+        replacer.set_evictable(1, true);
+        assert_eq!(replacer.evict(), Some(1));
+    }
+
+    #[test]
+    fn test_clock_pro_reads_shared_reference_bits() {
+        let mut replacer = ClockProReplacer::new(3);
+        assert!(replacer.uses_reference_bits());
+        let bits: Vec<_> = (0..3).map(|_| Arc::new(AtomicBool::new(false))).collect();
+        for (page_id, bit) in (1..=3).zip(&bits) {
+            replacer.track(page_id, bit);
+            replacer.set_evictable(page_id, true);
+        }
+
+        // an access recorded straight in the bit of page 1 during its
+        // test period promotes it, it is not evicted
+        bits[0].store(true, Ordering::Relaxed);
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.candidates(3), [3, 1]);
+
+        // evicted and remembered nodes leave free slots behind, the
+        // slab does not grow
+        for page_id in 10..100 {
+            access(&mut replacer, page_id);
+            assert!(replacer.evict().is_some());
+        }
+        assert!(replacer.nodes.len() <= 2 * 3 + 2);
+        assert_eq!(
+            replacer.positions.len(),
+            replacer.nodes.len() - replacer.free.len()
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use crate::storage::page::PageID;
+
+// marks the missing neighbour of the first and the last node
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    page_id: PageID,
+    prev: usize,
+    next: usize,
+}
+
+/// Ordered set of page ids, oldest first. Pushing a page that is
+/// already in the list moves it to the back
+///
+/// nodes live in a slab and link to their neighbours by index, so
+/// moving or removing a page is O(1). slots of removed pages are
+/// reused, the slab never holds more nodes than the list did at once
+#[derive(Debug)]
+pub struct PageList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    positions: HashMap<PageID, usize>,
+    head: usize,
+    tail: usize,
+}
+
+impl Default for PageList {
+    fn default() -> PageList {
+        PageList {
+            nodes: vec![],
+            free: vec![],
+            positions: HashMap::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+}
+
+impl PageList {
+    pub fn new() -> PageList {
+        PageList::default()
+    }
+
+    pub fn push_back(&mut self, page_id: PageID) {
+        self.remove(page_id);
+
+        let node = Node {
+            page_id,
+            prev: self.tail,
+            next: NIL,
+        };
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        match self.tail {
+            NIL => self.head = idx,
+            tail => self.nodes[tail].next = idx,
+        }
+        self.tail = idx;
+        self.positions.insert(page_id, idx);
+    }
+
+    /// Puts the page in front of the oldest one, e.g. back where
+    /// `PageList::pop_front` took it from
+    pub fn push_front(&mut self, page_id: PageID) {
+        self.remove(page_id);
+
+        let node = Node {
+            page_id,
+            prev: NIL,
+            next: self.head,
+        };
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        match self.head {
+            NIL => self.tail = idx,
+            head => self.nodes[head].prev = idx,
+        }
+        self.head = idx;
+        self.positions.insert(page_id, idx);
+    }
+
+    pub fn remove(&mut self, page_id: PageID) -> bool {
+        let Some(idx) = self.positions.remove(&page_id) else {
+            return false;
+        };
+
+        let Node { prev, next, .. } = self.nodes[idx];
+        match prev {
+            NIL => self.head = next,
+            prev => self.nodes[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.nodes[next].prev = prev,
+        }
+        self.free.push(idx);
+        true
+    }
+
+    pub fn pop_front(&mut self) -> Option<PageID> {
+        if self.head == NIL {
+            return None;
+        }
+
+        let page_id = self.nodes[self.head].page_id;
+        self.remove(page_id);
+        Some(page_id)
+    }
+
+    pub fn contains(&self, page_id: PageID) -> bool {
+        self.positions.contains_key(&page_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Page ids, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = PageID> + '_ {
+        let mut idx = self.head;
+        std::iter::from_fn(move || {
+            let node = self.nodes.get(idx)?;
+            idx = node.next;
+            Some(node.page_id)
+        })
+    }
+
+    /// Oldest page matching `predicate`
+    pub fn find_oldest(&self, predicate: impl Fn(PageID) -> bool) -> Option<PageID> {
+        self.iter().find(|page_id| predicate(*page_id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PageList;
+
+    #[test]
+    fn test_list_order_and_slot_reuse() {
+        let mut list = PageList::new();
+        for page_id in 1..=4 {
+            list.push_back(page_id);
+        }
+        list.push_back(2);
+        assert!(list.remove(3));
+        assert!(!list.remove(3));
+        assert_eq!(list.iter().collect::<Vec<_>>(), [1, 4, 2]);
+
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.find_oldest(|page_id| page_id != 4), Some(2));
+
+        // removed nodes leave free slots behind, the slab does not grow
+        for page_id in 10..100 {
+            list.push_back(page_id);
+            list.remove(page_id - 1);
+        }
+        assert_eq!(list.iter().collect::<Vec<_>>(), [4, 2, 99]);
+        assert!(list.nodes.len() <= 4);
+
+        list.push_front(99);
+        list.push_front(5);
+        assert_eq!(list.iter().collect::<Vec<_>>(), [5, 99, 4, 2]);
+
+        while list.pop_front().is_some() {}
+        assert!(list.is_empty());
+        assert_eq!(list.iter().count(), 0);
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::storage::page::PageID;
+
+use super::{list::PageList, Replacer};
+
+/// Evicts the evictable page whose most recent access is the oldest
+///
+/// pages are kept in access order, so recording an access is O(1)
+/// and eviction only walks past the pinned pages at the front
+#[derive(Debug, Default)]
+pub struct LruReplacer {
+    order: PageList,
+    // whether a tracked page may be evicted
+    nodes: HashMap<PageID, bool>,
+    evictable: usize,
+}
+
+impl LruReplacer {
+    pub fn new() -> LruReplacer {
+        LruReplacer::default()
+    }
+}
+
+impl Replacer for LruReplacer {
+    fn record_access(&mut self, page_id: PageID) {
+        self.nodes.entry(page_id).or_insert(false);
+        self.order.push_back(page_id);
+    }
+
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool) {
+        if let Some(node) = self.nodes.get_mut(&page_id) {
+            if *node != evictable {
+                *node = evictable;
+                if evictable {
+                    self.evictable += 1;
+                } else {
+                    self.evictable -= 1;
+                }
+            }
+        }
+    }
+
+    fn evict(&mut self) -> Option<PageID> {
+        if self.evictable == 0 {
+            return None;
+        }
+        let victim = self.order.find_oldest(|page_id| self.nodes[&page_id])?;
+
+        self.remove(victim);
+        Some(victim)
+    }
+
+    fn restore(&mut self, page_id: PageID, _referenced: &Arc<AtomicBool>) {
+        if self.nodes.contains_key(&page_id) {
+            return;
+        }
+
+        // victims are the oldest evictable page
+        self.nodes.insert(page_id, false);
+        self.order.push_front(page_id);
+    }
+
+    fn remove(&mut self, page_id: PageID) {
+        if let Some(evictable) = self.nodes.remove(&page_id) {
+            self.order.remove(page_id);
+            if evictable {
+                self.evictable -= 1;
+            }
+        }
+    }
+
+    fn candidates(&self, count: usize) -> Vec<PageID> {
+        self.order
+            .iter()
+            .filter(|page_id| self.nodes[page_id])
+            .take(count)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.evictable
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::storage::page::PageID;
+
+use super::Replacer;
+
+#[derive(Debug)]
+struct LruKNode {
+    // timestamps of the last k accesses, oldest first
+    history: VecDeque<u64>,
+    evictable: bool,
+}
+
+/// LRU-K evicts the page with the largest backward k-distance, the
+/// time since its k-th most recent access. Pages with fewer than k
+/// recorded accesses have an infinite distance and go first, ties
+/// between them are broken by their earliest access (plain LRU)
+#[derive(Debug)]
+pub struct LruKReplacer {
+    k: usize,
+    nodes: HashMap<PageID, LruKNode>,
+    current_timestamp: u64,
+    evictable: usize,
+    // access history of the last victim, handed back by
+    // `Replacer::restore`
+    last_evicted: Option<(PageID, VecDeque<u64>)>,
+}
+
+impl LruKReplacer {
+    pub fn new(k: usize) -> LruKReplacer {
+        assert!(k > 0, "LRU-K needs k of at least 1");
+
+        LruKReplacer {
+            k,
+            nodes: HashMap::new(),
+            current_timestamp: 0,
+            evictable: 0,
+            last_evicted: None,
+        }
+    }
+}
+
+impl LruKReplacer {
+    // (has a finite k-distance, k-th most recent access), the
+    // smallest key has the largest backward k-distance
+    fn eviction_key(&self, node: &LruKNode) -> (bool, u64) {
+        (
+            node.history.len() == self.k,
+            node.history.front().copied().unwrap_or(0),
+        )
+    }
+}
+
+impl Replacer for LruKReplacer {
+    fn record_access(&mut self, page_id: PageID) {
+        self.current_timestamp += 1;
+
+        let node = self.nodes.entry(page_id).or_insert(LruKNode {
+            history: VecDeque::with_capacity(self.k),
+            evictable: false,
+        });
+        if node.history.len() == self.k {
+            node.history.pop_front();
+        }
+        node.history.push_back(self.current_timestamp);
+    }
+
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool) {
+        if let Some(node) = self.nodes.get_mut(&page_id) {
+            if node.evictable != evictable {
+                node.evictable = evictable;
+                if evictable {
+                    self.evictable += 1;
+                } else {
+                    self.evictable -= 1;
+                }
+            }
+        }
+    }
+
+    fn evict(&mut self) -> Option<PageID> {
+        let victim = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.evictable)
+            .min_by_key(|(_, node)| self.eviction_key(node))
+            .map(|(page_id, _)| *page_id)?;
+
+        if let Some(node) = self.nodes.remove(&victim) {
+            self.evictable -= 1;
+            self.last_evicted = Some((victim, node.history));
+        }
+        Some(victim)
+    }
+
+    fn restore(&mut self, page_id: PageID, _referenced: &Arc<AtomicBool>) {
+        if self.nodes.contains_key(&page_id) {
+            return;
+        }
+
+        // without its history the page has an infinite distance
+        // and the earliest access, it is the next to go
+        let history = match self.last_evicted.take() {
+            Some((victim, history)) if victim == page_id => history,
+            _ => VecDeque::with_capacity(self.k),
+        };
+        self.nodes.insert(
+            page_id,
+            LruKNode {
+                history,
+                evictable: false,
+            },
+        );
+    }
+
+    fn remove(&mut self, page_id: PageID) {
+        if let Some(node) = self.nodes.remove(&page_id) {
+            if node.evictable {
+                self.evictable -= 1;
+            }
+        }
+    }
+
+    fn candidates(&self, count: usize) -> Vec<PageID> {
+        let mut pages: Vec<((bool, u64), PageID)> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.evictable)
+            .map(|(page_id, node)| (self.eviction_key(node), *page_id))
+            .collect();
+        pages.sort_unstable();
+
+        pages
+            .into_iter()
+            .take(count)
+            .map(|(_, page_id)| page_id)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.evictable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::buffer::replacer::{lru::LruReplacer, Replacer};
+
+    use super::LruKReplacer;
+
+    fn access_all(replacer: &mut dyn Replacer, pages: &[u32]) {
+        for page_id in pages {
+            replacer.record_access(*page_id);
+            replacer.set_evictable(*page_id, true);
+        }
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recent() {
+        let mut replacer = LruReplacer::new();
+        access_all(&mut replacer, &[1, 2, 3, 1]);
+        assert_eq!(replacer.size(), 3);
+
+        replacer.set_evictable(2, false);
+        assert_eq!(replacer.evict(), Some(3));
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), None);
+
+        replacer.set_evictable(2, true);
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.size(), 0);
+    }
+
+    #[test]
+    fn test_lru_k_infinite_distance_first() {
+        let mut replacer = LruKReplacer::new(2);
+        // 1 and 2 have two accesses, 3 and 4 only one
+        access_all(&mut replacer, &[1, 2, 3, 4, 1, 2]);
+        assert_eq!(replacer.size(), 4);
+
+        // fewer than k accesses, earliest first access goes first
+        assert_eq!(replacer.evict(), Some(3));
+        assert_eq!(replacer.evict(), Some(4));
+        // then the largest backward k-distance
+        assert_eq!(replacer.evict(), Some(1));
+        assert_eq!(replacer.evict(), Some(2));
+        assert_eq!(replacer.evict(), None);
+    }
+
+    #[test]
+    fn test_lru_k_uses_kth_access() {
+        let mut replacer = LruKReplacer::new(2);
+        access_all(&mut replacer, &[1, 1, 2, 2, 1]);
+
+        // 1's second most recent access is at t=2, 2's at t=3
+        assert_eq!(replacer.evict(), Some(1));
+
+        replacer.remove(2);
+        assert_eq!(replacer.size(), 0);
+        assert_eq!(replacer.evict(), None);
+    }
+}
@@ -0,0 +1,259 @@
+// replacement policies deciding which frame the cache
+// evicts when it is full
+//
+// a replacer only tracks page ids, the frames themselves
+// stay in the cache. frames start out non-evictable and
+// the cache marks them evictable while they are unpinned
+
+pub mod arc;
+pub mod clock;
+pub mod clock_pro;
+mod list;
+pub mod lru;
+pub mod lru_k;
+pub mod two_q;
+
+use std::{
+    fmt::Debug,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::storage::page::PageID;
+
+use arc::ArcReplacer;
+use clock::ClockReplacer;
+use clock_pro::ClockProReplacer;
+use lru::LruReplacer;
+use lru_k::LruKReplacer;
+use two_q::TwoQReplacer;
+
+pub trait Replacer: Debug + Send + Sync {
+    /// Records an access to the page at the current timestamp,
+    /// starts tracking the page if it is not tracked yet
+    fn record_access(&mut self, page_id: PageID);
+
+    /// Whether accesses are read from the bits handed to
+    /// `Replacer::track`. Setting the bit is then all an access
+    /// takes, the cache can pin a page without calling the replacer
+    fn uses_reference_bits(&self) -> bool {
+        false
+    }
+
+    /// Starts tracking the page like `Replacer::record_access`, with
+    /// `referenced` as its reference bit when the replacer uses them
+    fn track(&mut self, page_id: PageID, _referenced: &Arc<AtomicBool>) {
+        self.record_access(page_id)
+    }
+
+    /// Controls whether the page may be chosen by `Replacer::evict`
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool);
+
+    /// Picks a victim among the evictable pages and stops
+    /// tracking it
+    fn evict(&mut self) -> Option<PageID>;
+
+    /// Tracks a page `Replacer::evict` just returned again, e.g. one
+    /// the cache could not write back yet. The page goes back where
+    /// it was evicted from, as far as the policy still knows, and
+    /// unlike `Replacer::track` this does not count as an access
+    fn restore(&mut self, page_id: PageID, referenced: &Arc<AtomicBool>);
+
+    /// Stops tracking the page, e.g. after it was deleted
+    fn remove(&mut self, page_id: PageID);
+
+    /// Up to `count` evictable pages, roughly in the order
+    /// `Replacer::evict` would pick them, without changing any state
+    fn candidates(&self, count: usize) -> Vec<PageID>;
+
+    /// Number of evictable pages
+    fn size(&self) -> usize;
+}
+
+impl<R: Replacer + ?Sized> Replacer for Box<R> {
+    fn record_access(&mut self, page_id: PageID) {
+        (**self).record_access(page_id)
+    }
+
+    fn uses_reference_bits(&self) -> bool {
+        (**self).uses_reference_bits()
+    }
+
+    fn track(&mut self, page_id: PageID, referenced: &Arc<AtomicBool>) {
+        (**self).track(page_id, referenced)
+    }
+
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool) {
+        (**self).set_evictable(page_id, evictable)
+    }
+
+    fn evict(&mut self) -> Option<PageID> {
+        (**self).evict()
+    }
+
+    fn restore(&mut self, page_id: PageID, referenced: &Arc<AtomicBool>) {
+        (**self).restore(page_id, referenced)
+    }
+
+    fn remove(&mut self, page_id: PageID) {
+        (**self).remove(page_id)
+    }
+
+    fn candidates(&self, count: usize) -> Vec<PageID> {
+        (**self).candidates(count)
+    }
+
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+}
+
+/// Replacement policy picked when creating a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplacementPolicy {
+    #[default]
+    Lru,
+    // LRU-K with the given K
+    LruK(usize),
+    Clock,
+    ClockPro,
+    // scan resistant policies keeping ghost lists
+    // of recently evicted pages
+    Arc,
+    TwoQ,
+}
+
+impl ReplacementPolicy {
+    pub fn build(&self, max_frames: usize) -> Box<dyn Replacer> {
+        match *self {
+            Self::Lru => Box::new(LruReplacer::new()),
+            Self::LruK(k) => Box::new(LruKReplacer::new(k)),
+            Self::Clock => Box::new(ClockReplacer::new()),
+            Self::ClockPro => Box::new(ClockProReplacer::new(max_frames)),
+            Self::Arc => Box::new(ArcReplacer::new(max_frames)),
+            Self::TwoQ => Box::new(TwoQReplacer::new(max_frames)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{arc::ArcReplacer, two_q::TwoQReplacer, ReplacementPolicy, Replacer};
+
+    // keeps `capacity` pages resident like the cache does and
+    // returns the pages resident at the end
+    fn run(replacer: &mut dyn Replacer, capacity: usize, trace: &[u32]) -> Vec<u32> {
+        let mut resident: Vec<u32> = vec![];
+        for page_id in trace {
+            if !resident.contains(page_id) {
+                if resident.len() == capacity {
+                    let victim = replacer.evict().unwrap();
+                    resident.retain(|id| *id != victim);
+                }
+                resident.push(*page_id);
+            }
+            replacer.record_access(*page_id);
+            replacer.set_evictable(*page_id, true);
+        }
+        resident
+    }
+
+    // two hot pages accessed between cold ones, then a
+    // scan over more pages than fit in the cache
+    fn scan_trace() -> Vec<u32> {
+        let mut trace = vec![];
+        for round in 0..6 {
+            trace.extend([1, 2]);
+            trace.extend(10 + 3 * round..13 + 3 * round);
+        }
+        trace.extend(100..120);
+        trace
+    }
+
+    #[test]
+    fn test_two_q_is_scan_resistant() {
+        let mut replacer = TwoQReplacer::new(8);
+        let resident = run(&mut replacer, 8, &scan_trace());
+
+        assert!(resident.contains(&1));
+        assert!(resident.contains(&2));
+    }
+
+    #[test]
+    fn test_arc_is_scan_resistant() {
+        let mut replacer = ArcReplacer::new(8);
+        let resident = run(&mut replacer, 8, &scan_trace());
+
+        assert!(resident.contains(&1));
+        assert!(resident.contains(&2));
+    }
+
+    #[test]
+    fn test_pinned_pages_survive() {
+        for replacer in [
+            &mut ArcReplacer::new(2) as &mut dyn Replacer,
+            &mut TwoQReplacer::new(2),
+        ] {
+            replacer.record_access(1);
+            replacer.record_access(2);
+            replacer.set_evictable(2, true);
+
+            assert_eq!(replacer.evict(), Some(2));
+            assert_eq!(replacer.evict(), None);
+
+            replacer.remove(1);
+            assert_eq!(replacer.size(), 0);
+        }
+    }
+
+    #[test]
+    fn test_candidates_match_evictions() {
+        for policy in [
+            ReplacementPolicy::Lru,
+            ReplacementPolicy::LruK(2),
+            ReplacementPolicy::Clock,
+            ReplacementPolicy::ClockPro,
+            ReplacementPolicy::Arc,
+            ReplacementPolicy::TwoQ,
+        ] {
+            let mut replacer = policy.build(4);
+            run(&mut *replacer, 4, &[1, 2, 3, 4, 1, 2]);
+            replacer.set_evictable(3, false);
+
+            let candidates = replacer.candidates(4);
+            assert_eq!(candidates.len(), 3, "{policy:?}");
+            assert!(!candidates.contains(&3), "{policy:?}");
+            assert_eq!(replacer.candidates(1), candidates[..1], "{policy:?}");
+
+            // peeking does not change what is evicted
+            assert_eq!(replacer.evict(), Some(candidates[0]), "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_restored_victims_keep_their_position() {
+        for policy in [
+            ReplacementPolicy::Lru,
+            ReplacementPolicy::LruK(2),
+            ReplacementPolicy::Clock,
+            ReplacementPolicy::ClockPro,
+            ReplacementPolicy::Arc,
+            ReplacementPolicy::TwoQ,
+        ] {
+            let mut replacer = policy.build(4);
+            run(&mut *replacer, 4, &[1, 2, 3, 4]);
+            let victim = replacer.evict().unwrap();
+
+            // a victim that could not be written back is no ghost
+            // hit, it is not promoted past the pages added after it
+            replacer.restore(victim, &Arc::default());
+            replacer.set_evictable(victim, true);
+            run(&mut *replacer, 6, &[5, 6]);
+
+            assert_eq!(replacer.size(), 6, "{policy:?}");
+            assert_eq!(replacer.candidates(1), [victim], "{policy:?}");
+            assert_eq!(replacer.evict(), Some(victim), "{policy:?}");
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use crate::storage::page::PageID;
+
+use super::{list::PageList, Replacer};
+
+/// 2Q (Johnson, Shasha 1994). First time pages enter the FIFO `a1_in`
+/// and are evicted from there into the ghost FIFO `a1_out`. Only a page
+/// accessed again while remembered in `a1_out` is admitted into the
+/// LRU `am`, so pages touched once by a scan never reach `am`
+#[derive(Debug)]
+pub struct TwoQReplacer {
+    // resident size a1_in may grow to before it is evicted from
+    k_in: usize,
+    // number of ghosts remembered in a1_out
+    k_out: usize,
+
+    a1_in: PageList,
+    a1_out: PageList,
+    am: PageList,
+
+    evictable: HashMap<PageID, bool>,
+    evictable_count: usize,
+}
+
+impl TwoQReplacer {
+    /// Sizes a1_in to a quarter and a1_out to half of `capacity`,
+    /// the values suggested in the paper
+    pub fn new(capacity: usize) -> TwoQReplacer {
+        TwoQReplacer {
+            k_in: (capacity / 4).max(1),
+            k_out: (capacity / 2).max(1),
+            a1_in: PageList::new(),
+            a1_out: PageList::new(),
+            am: PageList::new(),
+            evictable: HashMap::new(),
+            evictable_count: 0,
+        }
+    }
+
+    fn prefer_a1_in(&self) -> bool {
+        self.a1_in.len() > self.k_in || self.am.is_empty()
+    }
+
+    fn evict_from(&mut self, from_a1_in: bool) -> Option<PageID> {
+        let evictable = &self.evictable;
+        let list = if from_a1_in {
+            &mut self.a1_in
+        } else {
+            &mut self.am
+        };
+
+        let victim = list.find_oldest(|page_id| evictable[&page_id])?;
+        list.remove(victim);
+
+        if from_a1_in {
+            self.a1_out.push_back(victim);
+            while self.a1_out.len() > self.k_out {
+                self.a1_out.pop_front();
+            }
+        }
+
+        self.evictable.remove(&victim);
+        self.evictable_count -= 1;
+        Some(victim)
+    }
+}
+
+impl Replacer for TwoQReplacer {
+    fn record_access(&mut self, page_id: PageID) {
+        if self.am.contains(page_id) {
+            self.am.push_back(page_id);
+            return;
+        }
+        if self.a1_in.contains(page_id) {
+            // correlated re-references while in a1_in do not count
+            return;
+        }
+
+        if self.a1_out.remove(page_id) {
+            self.am.push_back(page_id);
+        } else {
+            self.a1_in.push_back(page_id);
+        }
+        self.evictable.insert(page_id, false);
+    }
+
+    fn set_evictable(&mut self, page_id: PageID, evictable: bool) {
+        if let Some(current) = self.evictable.get_mut(&page_id) {
+            if *current != evictable {
+                *current = evictable;
+                if evictable {
+                    self.evictable_count += 1;
+                } else {
+                    self.evictable_count -= 1;
+                }
+            }
+        }
+    }
+
+    fn evict(&mut self) -> Option<PageID> {
+        if self.evictable_count == 0 {
+            return None;
+        }
+
+        let prefer_a1_in = self.prefer_a1_in();
+        self.evict_from(prefer_a1_in)
+            .or_else(|| self.evict_from(!prefer_a1_in))
+    }
+
+    fn restore(&mut self, page_id: PageID, _referenced: &Arc<AtomicBool>) {
+        if self.evictable.contains_key(&page_id) {
+            return;
+        }
+
+        // a ghost in a1_out was evicted from a1_in, everything
+        // else came from am. neither is a re-reference
+        if self.a1_out.remove(page_id) {
+            self.a1_in.push_front(page_id);
+        } else {
+            self.am.push_front(page_id);
+        }
+        self.evictable.insert(page_id, false);
+    }
+
+    fn remove(&mut self, page_id: PageID) {
+        if let Some(evictable) = self.evictable.remove(&page_id) {
+            if evictable {
+                self.evictable_count -= 1;
+            }
+        }
+        self.a1_in.remove(page_id);
+        self.a1_out.remove(page_id);
+        self.am.remove(page_id);
+    }
+
+    fn candidates(&self, count: usize) -> Vec<PageID> {
+        let (first, second) = if self.prefer_a1_in() {
+            (&self.a1_in, &self.am)
+        } else {
+            (&self.am, &self.a1_in)
+        };
+
+        first
+            .iter()
+            .chain(second.iter())
+            .filter(|page_id| self.evictable[page_id])
+            .take(count)
+            .collect()
+    }
+
+    fn size(&self) -> usize {
+        self.evictable_count
+    }
+}
@@ -1,115 +1,604 @@
 use std::{
-    fmt,
-    fs::{File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
-    ptr::write,
-    sync::{Arc, Mutex, RwLock},
+    collections::HashMap,
+    future::Future,
+    io, mem,
+    ops::Range,
+    path::Path,
+    pin::Pin,
+    sync::{mpsc, Arc},
+    task::{Context, Poll},
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+use parking_lot::{ArcRwLockReadGuard, ArcRwLockWriteGuard, Mutex, RawRwLock, RwLock};
+use tokio::sync::{oneshot, Notify};
+
 use crate::storage::{
     directory::PageDirector,
-    page::{Frame, PageID, FRAME_SIZE},
+    doublewrite::{DoubleWriteBuffer, DoubleWritePage, DOUBLE_WRITE_BATCH, DOUBLE_WRITE_EXTENSION},
+    file::{FileBackend, StorageBackend},
+    header::{FileHeader, HEADER_SIZE},
+    page::{Frame, PageHeader, PageID, FRAME_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE},
+};
+
+use crate::{
+    error::{Error, Result},
+    wal::{record::LogBody, LogManager, Lsn, TxnId, INVALID_LSN, INVALID_TXN, LOG_EXTENSION},
 };
 
-use super::cache::Cache;
+use super::{
+    arena::FrameBuf,
+    cache::{Cache, PageTable, PinCount, PinnedFrame},
+    recovery::{self, RecoveryReport},
+    replacer::ReplacementPolicy,
+};
 
 #[allow(unused)]
 pub struct DiskManager {
-    db_file: File,
+    backend: Arc<dyn StorageBackend>,
+    // true while the manager is open, cleared by `close`
     status: bool,
 
+    header: FileHeader,
     page_directory: PageDirector,
+    // offsets of the chain of pages holding the last
+    // persisted page directory
+    directory_pages: Vec<usize>,
     pub cache: Cache,
+    // page reads and writes go through the scheduler,
+    // header and directory pages are written directly
+    scheduler: Arc<DiskScheduler>,
+    // changes to pages are logged here before they reach the file
+    wal: Arc<LogManager>,
+    // write backs skipped because the frame was clean
+    avoided_writes: u64,
+    // outcome of the recovery run when the database was opened
+    recovery: Option<RecoveryReport>,
+    durability: Durability,
+}
+
+/// Outcome of `DiskManager::try_pin_page`
+pub enum PinOutcome {
+    Pinned(PinnedFrame),
+    // page is not cached, its content is being read
+    Pending(PendingRead),
 }
 
-#[derive(Debug, Clone)]
-pub enum Error {
-    DeletePageError,
-    WritePageError,
-    CacheFetchMiss,
+/// Write back of a frame in flight. The frame stays pinned until the
+/// write completed, so it is not evicted while the write may still
+/// fail, and is only marked clean once the write succeeded
+pub struct ScheduledFlush {
+    future: DiskFuture<()>,
+    frame: Arc<RwLock<Frame>>,
+    pins: PinCount,
+    // modifications of the frame when its write was scheduled
+    modifications: u64,
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Self::DeletePageError => write!(f, "Failed to perform operations to delete a page"),
-            Self::WritePageError => write!(f, "Failed to perform operations to write a page"),
-            Self::CacheFetchMiss => write!(f, "Frame flush requested is not in cache"),
+impl ScheduledFlush {
+    // the pin is released right away when the write could not
+    // be scheduled
+    fn new(
+        write: Result<DiskFuture<()>>,
+        (frame, pins): PinnedFrame,
+        modifications: u64,
+    ) -> Result<ScheduledFlush> {
+        match write {
+            Ok(future) => Ok(ScheduledFlush {
+                future,
+                frame,
+                pins,
+                modifications,
+            }),
+            Err(e) => {
+                pins.unpin();
+                Err(e)
+            }
+        }
+    }
+
+    /// Blocks until the write completed, see `DiskFuture::wait`. The
+    /// frame latch is waited for to mark the frame clean, so the disk
+    /// manager must not be held
+    pub fn wait(self) -> Result<()> {
+        let ScheduledFlush {
+            future,
+            frame,
+            pins,
+            modifications,
+        } = self;
+
+        let result = future.wait();
+        if result.is_ok() {
+            mark_clean(&mut frame.write(), modifications);
+        }
+        pins.unpin();
+        result?;
+        Ok(())
+    }
+
+    /// Like `ScheduledFlush::wait` without blocking the runtime. A
+    /// writer holding the frame latch once the write completed is not
+    /// waited for, the frame stays dirty and is written again later
+    pub async fn wait_async(self) -> Result<()> {
+        let ScheduledFlush {
+            future,
+            frame,
+            pins,
+            modifications,
+        } = self;
+
+        let result = future.await;
+        if let (Ok(_), Some(mut handler)) = (&result, frame.try_write()) {
+            mark_clean(&mut handler, modifications);
+        }
+        pins.unpin();
+        result?;
+        Ok(())
+    }
+
+    /// Like `ScheduledFlush::wait` but marks the frame clean without
+    /// taking its latch
+    ///
+    /// # Safety
+    ///
+    /// no other thread may access the page while this runs and no
+    /// reference into the frame may be held across the call
+    pub unsafe fn wait_unlatched(self) -> Result<()> {
+        let ScheduledFlush {
+            future,
+            frame,
+            pins,
+            modifications,
+        } = self;
+
+        let result = future.wait();
+        if result.is_ok() {
+            // SAFETY: the caller guarantees there is no concurrent access
+            mark_clean(unsafe { &mut *frame.data_ptr() }, modifications);
         }
+        pins.unpin();
+        result?;
+        Ok(())
     }
 }
 
-impl std::error::Error for Error {}
+// a frame modified while its write was in flight stays dirty,
+// the write does not hold the modification
+fn mark_clean(frame: &mut Frame, modifications: u64) {
+    if frame.modifications == modifications {
+        frame.dirty = false;
+    }
+}
+
+/// Outcome of `DiskManager::install_page`
+pub enum InstallOutcome {
+    Installed(PinnedFrame),
+    // the page was written back while it was read, the read is stale
+    Stale,
+    // the frame to evict has to be written back first, the read page
+    // is handed back to be installed once the write completed
+    WriteBack(ScheduledFlush, DiskPage),
+    // the log is not durable up to this LSN of the frame to evict,
+    // the caller forces it with the disk manager released and
+    // installs the handed back page again
+    LogBehind(Lsn, DiskPage),
+}
+
+/// Outcome of `DiskManager::schedule_flush`
+pub enum FlushAttempt {
+    // write of the frame scheduled, the frame stays pinned and
+    // dirty until the write completed
+    Scheduled(ScheduledFlush),
+    // page is not cached, there is nothing to write
+    NotCached,
+    // frame was not modified since it was last written
+    Clean,
+    // a writer holds the frame latch, the caller waits for
+    // it with the disk manager released and retries
+    Latched(Arc<RwLock<Frame>>),
+    // the log is not durable up to the page LSN yet, the caller
+    // forces it with the disk manager released and retries
+    LogBehind(Lsn),
+}
+
+// how long a flush waits for a writer holding the frame latch
+// before it fails with `Error::PagePinned`, a caller holding a
+// guard on the page itself would wait forever otherwise
+pub const FLUSH_LATCH_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct PendingRead {
+    pub page_id: PageID,
+    pub offset: usize,
+    epoch: u64,
+    pub future: DiskFuture,
+}
+
+// exclusive latch on a frame, held by the disk manager while the
+// write of the frame is scheduled
+type FrameLatch = ArcRwLockWriteGuard<RawRwLock, Frame>;
+
+/// When page writes of the disk manager reach stable storage. The
+/// header, the page directory and the log are always synced
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    // left to the os, only `close` syncs the db file
+    #[default]
+    NoSync,
+    // the db file is synced once flushing every page completes
+    SyncOnFlushAll,
+    // every page write is followed by an fdatasync
+    SyncPerPage,
+    // pages are written through a handle opened with O_DSYNC
+    Dsync,
+}
 
 impl DiskManager {
-    pub fn new(max_frames: usize, db_file: &str) -> DiskManager {
-        // let filename = db_file.clone().split(".").nth(0).unwrap();
-        // let log_file = format!("{filename}.log");
+    /// Creates an empty database, truncating any existing file
+    pub fn new(max_frames: usize, db_file: &str, policy: ReplacementPolicy) -> Result<DiskManager> {
+        let backend = Arc::new(FileBackend::create(Path::new(db_file))?);
+        DiskManager::create_with_backend(max_frames, backend, db_file, policy)
+    }
+
+    /// Creates an empty database on `backend`, overwriting whatever it
+    /// holds. The log and the double write buffer are sidecars of the
+    /// backend, `db_path` only names the database in messages
+    pub fn create_with_backend(
+        max_frames: usize,
+        backend: Arc<dyn StorageBackend>,
+        db_path: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<DiskManager> {
+        println!("[DEBUG][DiskManager] empty file opened");
+        let header = FileHeader::new(max_frames);
+        backend.set_len(0)?;
+        backend.write_at(&*header.encode(), 0)?;
+        // a buffer left by an earlier database would repair pages of this one
+        if let Some(double_write) = backend.sidecar(DOUBLE_WRITE_EXTENSION, false)? {
+            double_write.set_len(0)?;
+        }
+        let wal = Arc::new(LogManager::create(sidecar(&*backend, LOG_EXTENSION)?)?);
+        println!("[DEBUG][DiskManager] created {db_path}");
 
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(db_file)
-            .expect("disk manager failed to open file");
+        Ok(DiskManager::assemble(
+            max_frames,
+            backend,
+            (header, PageDirector::new(), vec![]),
+            wal,
+            policy,
+        ))
+    }
 
-        let size = file.metadata().unwrap().len();
+    /// Opens an existing database file without truncating it, validating
+    /// its header and reloading the page directory persisted by the last
+    /// `close`. A missing or empty file is initialised as a new database
+    pub fn open(
+        max_frames: usize,
+        db_file: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<DiskManager> {
+        let backend = Arc::new(FileBackend::open(Path::new(db_file))?);
+        DiskManager::open_with_backend(max_frames, backend, db_file, policy)
+    }
 
-        if size == 0 {
-            println!("[DEBUG][DiskManager] empty file opened");
-            // initialise with db file headers
+    /// Like `DiskManager::open` on the database stored in `backend`,
+    /// see `DiskManager::create_with_backend`
+    pub fn open_with_backend(
+        max_frames: usize,
+        backend: Arc<dyn StorageBackend>,
+        db_path: &str,
+        policy: ReplacementPolicy,
+    ) -> Result<DiskManager> {
+        let len = backend.len()?;
+        if len == 0 {
+            return DiskManager::create_with_backend(max_frames, backend, db_path, policy);
         }
 
+        let mut content = vec![0; len.min(HEADER_SIZE) as usize];
+        backend.read_at(&mut content, 0)?;
+        let header = FileHeader::decode(&content)?;
+
+        let mut page_directory = PageDirector::new();
+        let mut directory_pages = vec![];
+        if header.directory_offset != 0 {
+            let mut bytes = vec![];
+            let mut offset = header.directory_offset as usize;
+            while offset != 0 {
+                let mut page = [0; PAGE_SIZE as usize];
+                backend.read_at(&mut page, offset as u64)?;
+
+                let (header, content) = page.split_at(PAGE_HEADER_SIZE as usize);
+                let content: &[u8; FRAME_SIZE as usize] = content.try_into().unwrap();
+                if !PageHeader::decode(header.try_into().unwrap()).verify(content) {
+                    return Err(Error::ChecksumMismatch { page_id: 0, offset });
+                }
+                let (next, payload) = PageDirector::decode_page(content)?;
+                bytes.extend_from_slice(payload);
+                directory_pages.push(offset);
+                offset = next;
+            }
+            page_directory = PageDirector::decode(&bytes)?;
+        }
+
+        println!(
+            "[DEBUG][DiskManager] opened {db_path} with {} pages",
+            page_directory.current_mapsize()
+        );
+        let wal = Arc::new(LogManager::open(sidecar(&*backend, LOG_EXTENSION)?)?);
+
+        // pages torn by a crash are repaired before anything reads them,
+        // directory pages are written in place and never torn. an empty
+        // buffer belongs to a database that never enabled it
+        let mut double_write = None;
+        let buffer_file = backend
+            .sidecar(DOUBLE_WRITE_EXTENSION, false)?
+            .filter(|file| file.len().is_ok_and(|len| len > 0));
+        if let Some(file) = buffer_file {
+            let buffer = DoubleWriteBuffer::open(file, DISK_WORKERS)?;
+            let mapped = mapped_offsets(&page_directory, &wal, header.directory_lsn)?;
+            let repaired = buffer.repair(&*backend, &mapped)?;
+            println!(
+                "[DEBUG][DiskManager] repaired {} torn pages",
+                repaired.len()
+            );
+            double_write = Some(Arc::new(buffer));
+        }
+
+        let mut disk_manager = DiskManager::assemble(
+            max_frames,
+            backend,
+            (header, page_directory, directory_pages),
+            wal,
+            policy,
+        );
+        disk_manager.scheduler.set_double_write(double_write);
+
+        // the log is reset on close, records left in it mean the
+        // database was not closed cleanly
+        if !disk_manager.wal.is_empty() {
+            disk_manager.recovery = Some(recovery::recover(&mut disk_manager)?);
+        }
+
+        Ok(disk_manager)
+    }
+
+    fn assemble(
+        max_frames: usize,
+        backend: Arc<dyn StorageBackend>,
+        (header, page_directory, directory_pages): (FileHeader, PageDirector, Vec<usize>),
+        wal: Arc<LogManager>,
+        policy: ReplacementPolicy,
+    ) -> DiskManager {
+        let scheduler = Arc::new(DiskScheduler::with_log(
+            Arc::clone(&backend),
+            DISK_WORKERS,
+            Arc::clone(&wal),
+        ));
+
         DiskManager {
-            db_file: file,
+            backend,
             status: true,
-            page_directory: PageDirector::new(),
-            cache: Cache::new(max_frames),
+            header,
+            page_directory,
+            directory_pages,
+            cache: Cache::with_replacer(max_frames, policy.build(max_frames)),
+            scheduler,
+            wal,
+            avoided_writes: 0,
+            recovery: None,
+            durability: Durability::NoSync,
         }
     }
 
-    pub fn size(self) -> usize {
-        self.cache.max_frames
+    /// Writes the page directory into a fresh chain of directory pages
+    /// and points the file header at it, so the database can be reopened
+    /// with `DiskManager::open`
+    pub fn persist_directory(&mut self) -> Result<()> {
+        self.ensure_open()?;
+        let pages = self.page_directory.encode_pages(&self.directory_pages)?;
+
+        for (offset, content) in pages.iter() {
+            let header = PageHeader::new(INVALID_LSN, content);
+            self.backend
+                .write_at(&encode_page(&header, content), *offset as u64)?;
+        }
+        // the new chain has to be on disk before the header points to it
+        self.backend.sync()?;
+
+        self.directory_pages = pages.iter().map(|(offset, _)| *offset).collect();
+        self.header.directory_offset = self.directory_pages[0] as u64;
+        self.header.directory_lsn = self.wal.next_lsn();
+        self.backend.write_at(&*self.header.encode(), 0)?;
+        self.backend.sync()?;
+
+        Ok(())
     }
 
-    pub fn new_page(&mut self) -> PageID {
-        let len = self.db_file.metadata().unwrap().len();
+    /// Rolls back unfinished transactions, flushes every cached frame
+    /// and persists the page directory. Called on drop as well, calling
+    /// it more than once is a no-op
+    pub fn close(&mut self) -> Result<()> {
+        if !self.status {
+            return Ok(());
+        }
 
-        if !self.page_directory.can_accomodate() {
-            self.db_file.set_len(len + FRAME_SIZE).unwrap();
-            println!(
-                "[DEBUG][DiskManager] extending file size to add new page to {}",
-                self.db_file.metadata().unwrap().len()
-            );
+        // pages are only reached through the disk manager, which
+        // fails with `Error::Closed` from now on
+        self.cache.disable_page_table();
+        recovery::rollback(self, self.wal.active_transactions())?;
+        self.write_back_all()?;
+
+        self.status = false;
+        Ok(())
+    }
+
+    /// Writes pages through a double write buffer from now on, so a
+    /// page torn by a crash can be repaired. The buffer is a sidecar of
+    /// the db file and stays in use when the database is reopened
+    pub fn enable_double_write(&mut self) -> Result<()> {
+        self.ensure_open()?;
+        if self.scheduler.double_write_enabled() {
+            return Ok(());
         }
 
-        let (registerd_page, offset) = self.page_directory.register_new_page();
+        let file = sidecar(&*self.backend, DOUBLE_WRITE_EXTENSION)?;
+        let buffer = DoubleWriteBuffer::create(file, DISK_WORKERS)?;
+        self.scheduler.set_double_write(Some(Arc::new(buffer)));
+        Ok(())
+    }
+
+    /// Changes when page writes reach stable storage, see `Durability`.
+    /// Writes completed before the switch are synced right away
+    pub fn set_durability(&mut self, durability: Durability) -> Result<()> {
+        self.ensure_open()?;
+        let write_file = match durability {
+            Durability::Dsync => self.backend.dsync_handle()?,
+            _ => None,
+        };
+        // a backend without a dsync handle syncs every write instead
+        let sync_writes = durability == Durability::SyncPerPage
+            || (durability == Durability::Dsync && write_file.is_none());
+        self.scheduler.set_write_file(write_file);
+        self.scheduler.set_sync_writes(sync_writes);
+        self.backend.sync()?;
+
+        println!("[DEBUG][DiskManager] durability set to {durability:?}");
+        self.durability = durability;
+        Ok(())
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// Rolls back a running transaction, guards on pages it
+    /// changed have to be dropped first
+    pub fn abort(&mut self, txn_id: TxnId) -> Result<()> {
+        self.ensure_open()?;
+        let lsn = self.wal.append(txn_id, LogBody::Abort)?;
+        recovery::rollback(self, vec![(txn_id, lsn)])?;
+        Ok(())
+    }
+
+    /// Writes back every cached frame and persists the page directory,
+    /// after which the log is no longer needed and is reset
+    pub fn write_back_all(&mut self) -> Result<()> {
+        for page_id in self.cache.page_ids() {
+            if let Some(frame) = self.cache.peek_frame(page_id) {
+                self.flush_frame(page_id, frame)?;
+            }
+        }
+        // the checkpoint is about to disappear with the log
+        self.header.checkpoint_lsn = INVALID_LSN;
+        self.persist_directory()?;
+        self.wal.reset()
+    }
+
+    /// Points the master record in the file header at a completed
+    /// checkpoint, recovery starts its analysis there
+    pub fn write_master_record(&mut self, checkpoint_lsn: Lsn) -> Result<()> {
+        self.ensure_open()?;
+        self.header.checkpoint_lsn = checkpoint_lsn;
+        self.backend.write_at(&*self.header.encode(), 0)?;
+        self.backend.sync()?;
+        Ok(())
+    }
+
+    /// LSN of the last completed checkpoint, `INVALID_LSN` if there
+    /// was none since the log was reset
+    pub fn checkpoint_lsn(&self) -> Lsn {
+        self.header.checkpoint_lsn
+    }
+
+    /// Report of the recovery run on open, `None` when the database
+    /// was closed cleanly
+    pub fn recovery_report(&self) -> Option<&RecoveryReport> {
+        self.recovery.as_ref()
+    }
+
+    pub fn size(self) -> usize {
+        self.cache.max_frames
+    }
+
+    pub fn new_page(&mut self) -> Result<PageID> {
+        self.check_writable()?;
+        // taken first, so a full arena fails before anything is registered
+        let buffer = self.cache.frame_buffer()?;
+        let (registerd_page, offset, reused) = self.page_directory.register_new_page();
         println!(
-            "[DEBUG][DiskManager] New page {registerd_page} with size {FRAME_SIZE} created with offset {offset}"
+            "[DEBUG][DiskManager] New page {registerd_page} with size {PAGE_SIZE} created with offset {offset}"
         );
+        let logged = self.wal.append(
+            INVALID_TXN,
+            LogBody::NewPage {
+                page_id: registerd_page,
+                offset,
+            },
+        );
+        if let Err(e) = logged {
+            self.page_directory.remove_page(registerd_page)?;
+            return Err(e);
+        }
 
-        let mut reader = BufReader::new(&self.db_file);
-        reader.seek(SeekFrom::Start(offset as u64)).unwrap();
+        // a page that can not be read is taken out of the directory
+        // again, and out of the log, so it does not leak
+        let (header, content) = match self.read_new_page(registerd_page, offset, reused, buffer) {
+            Ok(page) => page,
+            Err(e) => {
+                self.page_directory.remove_page(registerd_page)?;
+                let unlogged = self.wal.append(
+                    INVALID_TXN,
+                    LogBody::DeletePage {
+                        page_id: registerd_page,
+                    },
+                );
+                if let Err(e) = unlogged {
+                    println!(
+                        "[DEBUG][DiskManager] failed to log removal of page {registerd_page}: {e}"
+                    );
+                }
+                return Err(e);
+            }
+        };
 
-        let mut content: [u8; FRAME_SIZE as usize] = [0; FRAME_SIZE as usize];
-        reader
-            .read_exact(&mut content)
-            .unwrap_or_else(|_| panic!("failed to read {FRAME_SIZE} bytes from offset {offset}"));
+        // the page exists on disk even if every frame is pinned or
+        // the victim can not be written back, it just is not brought
+        // into the cache yet
+        let cached = self.make_room().and_then(|_| {
+            self.cache
+                .put_frame(registerd_page, offset, header.page_lsn, content)
+                .map_err(Error::from)
+        });
+        if let Err(e) = cached {
+            println!("[DEBUG][DiskManager] new page {registerd_page} not cached: {e}");
+        }
 
-        assert_eq!(content.len(), FRAME_SIZE as usize);
+        Ok(registerd_page)
+    }
 
-        let evict = self
-            .cache
-            .put_frame(registerd_page, offset, Box::new(content));
-        if let Some(frame) = evict {
-            let _ = self.flush_frame(frame);
+    // reads the slot of a page registered by `DiskManager::new_page`
+    // into `buffer`, growing the file to hold it first
+    fn read_new_page(
+        &mut self,
+        page_id: PageID,
+        offset: usize,
+        reused: bool,
+        buffer: FrameBuf,
+    ) -> Result<DiskPage> {
+        // a reused slot may have belonged to a page whose deletion is
+        // only in the log, it has to be durable before the slot is
+        // reused. a slot past the end of the file never held a page
+        if reused {
+            self.wal.flush_all()?;
         }
+        self.extend_file(offset)?;
 
-        registerd_page
+        let (header, content) = self.scheduler.read(page_id, offset, buffer).wait()?;
+        verify_page(page_id, offset, &header, &content)?;
+        Ok((header, content))
     }
 
-    /// TODO: proper error types
     /// Remove page from disk and memory. Find all traces of the page
     /// where it is being used and unallocate from memory
     ///
@@ -118,140 +607,1164 @@ impl DiskManager {
     /// slots that can be taken up by `DiskManager::new_page` the next time around
     /// while allocating a new page
     ///
-    /// todo: implement pins
-    pub fn delete_page(&mut self, page_id: PageID) -> Result<(), Box<dyn std::error::Error>> {
+    /// a pinned page can not be deleted
+    pub fn delete_page(&mut self, page_id: PageID) -> Result<()> {
+        self.check_writable()?;
         let query_page = self.page_directory.query_page(page_id);
         if query_page.is_none() {
-            return Err(Box::new(Error::DeletePageError));
+            return Err(Error::PageNotFound(page_id));
         }
 
-        let page_dir_delte = self.page_directory.remove_page(page_id);
-        if page_dir_delte.is_err() {
-            return page_dir_delte;
-        }
+        // drop the frame without writing it back, the slot is free now
+        self.cache.evict_frame(page_id)?;
+        self.page_directory.remove_page(page_id)?;
+        self.wal
+            .append(INVALID_TXN, LogBody::DeletePage { page_id })?;
+        self.wal.page_deleted(page_id);
 
         Ok(())
     }
 
-    #[allow(unused)]
-    pub fn write_page(
-        &mut self,
-        page_id: PageID,
-        bytes: Box<[u8; FRAME_SIZE as usize]>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // first check if a page is in memory or not
-        // the lookup will bring the frame to memory if not present
-        let mut frame = self.load_frame(page_id);
+    // grows the file so the slot at `offset` can be read
+    fn extend_file(&mut self, offset: usize) -> io::Result<()> {
+        let len = self.backend.len()?;
+        if len < offset as u64 + PAGE_SIZE {
+            self.backend.set_len(offset as u64 + PAGE_SIZE)?;
+            println!(
+                "[DEBUG][DiskManager] extending file size to add new page to {}",
+                offset as u64 + PAGE_SIZE
+            );
+        }
+        Ok(())
+    }
+
+    // pages can not be touched once the database was closed, the
+    // log was reset and the directory persisted
+    fn ensure_open(&self) -> Result<()> {
+        if self.status {
+            Ok(())
+        } else {
+            Err(Error::Closed)
+        }
+    }
 
-        if frame.is_none() {
-            return Err(Box::new(Error::WritePageError));
+    /// Fails when pages can not be changed, because the database was
+    /// closed or a write back failed for good, see `DiskManager::read_only`
+    pub fn check_writable(&self) -> Result<()> {
+        self.ensure_open()?;
+        match self.read_only() {
+            Some(kind) => Err(Error::ReadOnly(kind)),
+            None => Ok(()),
         }
+    }
+
+    /// Kind of the error that put the pool in read only mode, `None`
+    /// while it is writable. A write back failing in a way retrying
+    /// does not fix, like a full disk, switches to read only mode.
+    /// Cached pages can still be read but no page can be changed
+    pub fn read_only(&self) -> Option<io::ErrorKind> {
+        self.scheduler.write_failure()
+    }
 
-        let frame = frame.unwrap();
+    /// Leaves read only mode once the cause is fixed, e.g. disk space
+    /// was freed. Dirty frames are written back when they are evicted
+    /// or flushed, as before
+    pub fn resume_writes(&mut self) {
+        println!("[DEBUG][DiskManager] resuming writes");
+        self.scheduler.clear_write_failure();
+    }
 
-        let mut handler = frame.write().unwrap();
-        handler.content = bytes;
-        handler.dirty = true;
+    pub fn contains_page(&self, page_id: PageID) -> bool {
+        self.page_directory.query_page(page_id).is_some()
+    }
+
+    /// LSN below which every directory change is in the persisted
+    /// page directory
+    pub fn directory_lsn(&self) -> Lsn {
+        self.header.directory_lsn
+    }
+
+    /// Replays a page registration found in the log
+    pub fn redo_new_page(&mut self, page_id: PageID, offset: usize) -> Result<()> {
+        self.page_directory.restore_page(page_id, offset);
+        self.extend_file(offset)?;
+        Ok(())
+    }
+
+    /// Replays a page deletion found in the log
+    pub fn redo_delete_page(&mut self, page_id: PageID) -> Result<()> {
+        if self.contains_page(page_id) {
+            self.cache.evict_frame(page_id)?;
+            self.page_directory.remove_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Overwrites the content of a page at `offset` with `bytes`,
+    /// the frame is only marked dirty and written back later. Fails
+    /// with `Error::PagePinned` while a guard holds the frame latch
+    pub fn write_at(&mut self, page_id: PageID, offset: usize, bytes: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        let range = page_range(page_id, offset, bytes.len())?;
+        // the lookup will bring the frame to memory if not present
+        let frame = self.load_frame(page_id)?;
+
+        // waiting for the latch here would hold the disk manager
+        // while the guard holder may be waiting for it
+        let Some(mut handler) = frame.try_write() else {
+            return Err(Error::PagePinned(page_id));
+        };
+        handler.content[range].copy_from_slice(bytes);
+        handler.mark_dirty();
         drop(handler);
 
         Ok(())
     }
 
-    #[allow(unused)]
-    pub fn read_page(&mut self, page_id: PageID) -> Box<[u8; FRAME_SIZE as usize]> {
-        let frame = self.load_frame(page_id);
+    /// Copies the content of a page at `offset` into `buf`, see
+    /// `DiskManager::write_at`
+    pub fn read_at(&mut self, page_id: PageID, offset: usize, buf: &mut [u8]) -> Result<()> {
+        let range = page_range(page_id, offset, buf.len())?;
+        let frame = self.load_frame(page_id)?;
+
+        let Some(handler) = frame.try_read() else {
+            return Err(Error::PagePinned(page_id));
+        };
+        buf.copy_from_slice(&handler.content[range]);
+        Ok(())
+    }
+
+    /// Brings a page into the cache and pins it, the frame stays
+    /// resident until the returned pin count drops back
+    pub fn pin_page(&mut self, page_id: PageID) -> Result<PinnedFrame> {
+        self.load_frame(page_id)?;
+        self.cache
+            .pin_frame(page_id)
+            .ok_or(Error::PageNotFound(page_id))
+    }
 
-        if frame.is_none() {
-            panic!("Failed to load frame");
+    /// Non blocking variant of `DiskManager::pin_page`. A cached page
+    /// is pinned right away, otherwise a read is scheduled and the
+    /// page has to be installed with `DiskManager::install_page` once
+    /// the read completes. Fails with `Error::NoFreeFrames` while
+    /// every buffer of the frame arena is held
+    pub fn try_pin_page(&mut self, page_id: PageID) -> Result<PinOutcome> {
+        self.ensure_open()?;
+        if let Some(pinned) = self.cache.pin_frame(page_id) {
+            return Ok(PinOutcome::Pinned(pinned));
         }
 
-        let frame = frame.unwrap();
+        let offset = self
+            .page_directory
+            .query_page(page_id)
+            .ok_or(Error::PageNotFound(page_id))?;
 
-        return Box::clone(&frame.read().unwrap().content);
+        // the page is read into a buffer of the frame arena, which
+        // the frame keeps once the page is installed
+        let buffer = self.cache.frame_buffer()?;
+        Ok(PinOutcome::Pending(PendingRead {
+            page_id,
+            offset,
+            epoch: self.write_epoch(page_id),
+            future: self.scheduler.read(page_id, offset, buffer),
+        }))
     }
 
-    /// Method flushes dirty pages (pages that have been modified)
-    /// to disk safely, while having a lock on the frame
-    pub fn flush_page(&mut self, page_id: PageID) -> Result<(), Box<dyn std::error::Error>> {
-        let frame = self.cache.lookup_frame(page_id);
-        if frame.is_none() {
-            return Err(Box::new(Error::CacheFetchMiss));
+    /// Adds a page read by `DiskManager::try_pin_page` to the cache
+    /// and pins it. A dirty frame in the way is not waited on, its
+    /// write back is scheduled and returned along with the read page.
+    /// The frame stays pinned and dirty until the write completed, so
+    /// a failed write loses nothing, and the caller installs the page
+    /// again. Neither is the log forced for it, `InstallOutcome::LogBehind`
+    /// leaves that to the caller
+    ///
+    /// returns `InstallOutcome::Stale` when the page was written back
+    /// while the read was in flight, the caller reads it again
+    pub fn install_page(
+        &mut self,
+        read: &PendingRead,
+        (header, content): DiskPage,
+    ) -> Result<InstallOutcome> {
+        // someone else installed it in the meantime
+        if let Some(pinned) = self.cache.pin_frame(read.page_id) {
+            return Ok(InstallOutcome::Installed(pinned));
         }
+        if self.write_epoch(read.page_id) != read.epoch
+            || self.page_directory.query_page(read.page_id) != Some(read.offset)
+        {
+            return Ok(InstallOutcome::Stale);
+        }
+        verify_page(read.page_id, read.offset, &header, &content)?;
 
-        let frame = frame.unwrap();
-        let mut writer = BufWriter::new(&self.db_file);
-        let handler = frame.write().unwrap();
+        while let Some(victim) = self.select_victim()? {
+            let frame = self
+                .cache
+                .peek_frame(victim)
+                .ok_or(Error::PageNotFound(victim))?;
+            // pinned through the page table since it was picked
+            let Some(handler) = frame.try_write_arc() else {
+                self.cache.keep_victim(victim);
+                continue;
+            };
+            if handler.dirty && !self.wal.is_durable(handler.page_lsn) {
+                let lsn = handler.page_lsn;
+                drop(handler);
+                self.cache.keep_victim(victim);
+                return Ok(InstallOutcome::LogBehind(lsn, (header, content)));
+            }
+            if handler.dirty {
+                let pinned = self
+                    .cache
+                    .pin_unrecorded(victim)
+                    .ok_or(Error::PageNotFound(victim))?;
+                let flush = self.schedule_pinned_write(handler, pinned);
+                self.cache.keep_victim(victim);
+                return Ok(InstallOutcome::WriteBack(flush?, (header, content)));
+            }
+            drop(handler);
+            self.avoided_writes += 1;
+            if self.cache.remove_victim(victim).is_some() {
+                break;
+            }
+        }
 
-        writer.seek(SeekFrom::Start(handler.offset as u64)).unwrap();
-        writer.write_all(&*handler.content).unwrap();
-        writer.flush().unwrap();
+        self.cache
+            .put_frame(read.page_id, read.offset, header.page_lsn, content)?;
+        let pinned = self
+            .cache
+            .pin_frame(read.page_id)
+            .ok_or(Error::PageNotFound(read.page_id))?;
+        Ok(InstallOutcome::Installed(pinned))
+    }
 
-        drop(writer);
-        drop(handler);
+    /// Notified whenever a cached frame is unpinned
+    pub fn frame_released(&self) -> Arc<Notify> {
+        self.cache.frame_released()
+    }
 
-        Ok(())
+    /// Pins cached pages without locking the disk manager, see
+    /// `PageTable`
+    pub fn page_table(&self) -> PageTable {
+        self.cache.page_table()
+    }
+
+    fn write_epoch(&self, page_id: PageID) -> u64 {
+        self.scheduler.write_epoch(page_id)
+    }
+
+    // writes the frame from its own memory, the header is encoded in
+    // front of its content and the frame stays latched for reads
+    // until the write completed
+    //
+    // the log has to be durable up to the last change applied to
+    // the frame before the frame itself may reach the disk
+    fn schedule_write(&mut self, mut handler: FrameLatch) -> Result<DiskFuture<()>> {
+        self.wal.flush(handler.page_lsn)?;
+        let header = PageHeader::new(handler.page_lsn, &handler.content);
+        handler.content.page_mut()[..PAGE_HEADER_SIZE as usize].copy_from_slice(&header.encode());
+
+        let (page_id, offset) = (handler.page_id, handler.offset);
+        let page = PageImage::Frame(ArcRwLockWriteGuard::downgrade(handler));
+        Ok(self.scheduler.write(page_id, offset, page))
+    }
+
+    // writes a copy of a frame whose latch the caller holds itself
+    fn schedule_snapshot_write(&mut self, frame: &Frame) -> Result<DiskFuture<()>> {
+        self.wal.flush(frame.page_lsn)?;
+        let page = PageImage::snapshot(frame);
+        Ok(self.scheduler.write(frame.page_id, frame.offset, page))
     }
 
-    pub fn flush_frame(
+    // schedules the write back of a frame pinned for it, `handler`
+    // latching the same frame. The pin is released once the write
+    // completed, or right away if it can not be scheduled
+    fn schedule_pinned_write(
         &mut self,
-        frame: Arc<RwLock<Frame>>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut writer = BufWriter::new(&self.db_file);
-        let handler = frame.read().unwrap();
+        handler: FrameLatch,
+        pinned: PinnedFrame,
+    ) -> Result<ScheduledFlush> {
+        let modifications = handler.modifications;
+        let write = self.schedule_write(handler);
+        ScheduledFlush::new(write, pinned, modifications)
+    }
 
-        writer.seek(SeekFrom::Start(handler.offset as u64)).unwrap();
-        writer.write_all(&*handler.content).unwrap();
-        writer.flush().unwrap();
+    /// Method flushes dirty pages (pages that have been modified)
+    /// to disk safely, while having a lock on the frame. Clean
+    /// pages are skipped. The latch is only tried, a page latched by
+    /// a guard fails with `Error::PagePinned`, `DiskManager::schedule_flush`
+    /// lets the caller wait for it with the disk manager released
+    pub fn flush_page(&mut self, page_id: PageID) -> Result<()> {
+        self.ensure_open()?;
+        let Some(frame) = self.cache.peek_frame(page_id) else {
+            return Err(Error::PageNotCached(page_id));
+        };
+
+        let Some(handler) = frame.try_write_arc() else {
+            return Err(Error::PagePinned(page_id));
+        };
+        if !handler.dirty {
+            self.avoided_writes += 1;
+            return Ok(());
+        }
+
+        let modifications = handler.modifications;
+        self.schedule_write(handler)?.wait()?;
+        // latched by a guard as soon as the write released it, the
+        // frame stays dirty and is written again later
+        if let Some(mut handler) = frame.try_write() {
+            mark_clean(&mut handler, modifications);
+        }
+
+        Ok(())
+    }
+
+    /// Schedules the write back of a cached page without waiting for
+    /// it. The frame latch is only tried, never waited on, so the
+    /// disk manager is not held up by a writer holding a guard. The
+    /// log is not forced either, see `FlushAttempt::LogBehind`
+    pub fn schedule_flush(&mut self, page_id: PageID) -> Result<FlushAttempt> {
+        let Some(pinned) = self.flush_target(page_id)? else {
+            return Ok(FlushAttempt::NotCached);
+        };
+        // exclusive, so the write holds no half done modification
+        let Some(handler) = pinned.0.try_write_arc() else {
+            pinned.1.unpin();
+            return Ok(FlushAttempt::Latched(pinned.0));
+        };
+        if !handler.dirty {
+            pinned.1.unpin();
+            self.avoided_writes += 1;
+            return Ok(FlushAttempt::Clean);
+        }
+        if !self.wal.is_durable(handler.page_lsn) {
+            pinned.1.unpin();
+            return Ok(FlushAttempt::LogBehind(handler.page_lsn));
+        }
+
+        let flush = self.schedule_pinned_write(handler, pinned)?;
+        Ok(FlushAttempt::Scheduled(flush))
+    }
+
+    /// Like `DiskManager::schedule_flush` but copies the frame without
+    /// taking its latch
+    ///
+    /// # Safety
+    ///
+    /// no other thread may access the page while this runs, e.g. the
+    /// caller holds the page's `WritePageGuard` itself, and no reference
+    /// into the frame may be held across the call
+    pub unsafe fn schedule_flush_unlatched(&mut self, page_id: PageID) -> Result<FlushAttempt> {
+        let Some(pinned) = self.flush_target(page_id)? else {
+            return Ok(FlushAttempt::NotCached);
+        };
+
+        // SAFETY: the caller guarantees there is no concurrent access
+        let handler = unsafe { &*pinned.0.data_ptr() };
+        if !handler.dirty {
+            pinned.1.unpin();
+            self.avoided_writes += 1;
+            return Ok(FlushAttempt::Clean);
+        }
+
+        let modifications = handler.modifications;
+        let write = self.schedule_snapshot_write(handler);
+        let flush = ScheduledFlush::new(write, pinned, modifications)?;
+        Ok(FlushAttempt::Scheduled(flush))
+    }
+
+    // the cached frame of a page to flush, pinned so it is not
+    // evicted while its write is in flight
+    fn flush_target(&self, page_id: PageID) -> Result<Option<PinnedFrame>> {
+        self.ensure_open()?;
+        if self.page_directory.query_page(page_id).is_none() {
+            return Err(Error::PageNotFound(page_id));
+        }
+        Ok(self.cache.pin_unrecorded(page_id))
+    }
+
+    /// Schedules write backs of the dirty frames among the `max_pages`
+    /// unpinned frames closest to eviction. Frames latched by a writer,
+    /// or whose log records are not durable yet, are left for a later
+    /// round instead of forcing the log. The frames stay pinned and
+    /// dirty until their write completed, see `ScheduledFlush`
+    pub fn schedule_background_flush(&mut self, max_pages: usize) -> Vec<ScheduledFlush> {
+        let mut scheduled = vec![];
+        // the writes would fail again
+        if self.read_only().is_some() {
+            return scheduled;
+        }
+
+        for page_id in self.cache.eviction_candidates(max_pages) {
+            let Some(pinned) = self.cache.pin_unrecorded(page_id) else {
+                continue;
+            };
+            let frame = Arc::clone(&pinned.0);
+            match frame.try_write_arc() {
+                Some(handler) if handler.dirty && self.wal.is_durable(handler.page_lsn) => {
+                    if let Ok(flush) = self.schedule_pinned_write(handler, pinned) {
+                        scheduled.push(flush);
+                    }
+                }
+                _ => pinned.1.unpin(),
+            };
+        }
+
+        scheduled
+    }
+
+    /// Writes back a frame leaving the cache, clean frames are
+    /// already on disk and skipped. Fails with `Error::PagePinned`
+    /// instead of waiting while a guard holds the frame latch
+    pub fn flush_frame(&mut self, page_id: PageID, frame: Arc<RwLock<Frame>>) -> Result<()> {
+        let Some(handler) = frame.try_write_arc() else {
+            return Err(Error::PagePinned(page_id));
+        };
+        if !handler.dirty {
+            self.avoided_writes += 1;
+            return Ok(());
+        }
+
+        self.schedule_write(handler)?.wait()?;
 
-        drop(writer);
-        drop(handler);
         drop(frame); // free from memory
         Ok(())
     }
 
-    fn load_frame(&mut self, page_id: PageID) -> Option<Arc<RwLock<Frame>>> {
+    // evicts a frame when the cache is full. a dirty victim is written
+    // back while it is still cached, if the write fails it stays cached
+    // and the error is returned so no change is lost
+    //
+    // a victim pinned through the page table after it was picked
+    // stays cached as well, the next one is tried instead
+    fn make_room(&mut self) -> Result<()> {
+        while let Some(victim) = self.select_victim()? {
+            let frame = self
+                .cache
+                .peek_frame(victim)
+                .ok_or(Error::PageNotFound(victim))?;
+
+            match self.flush_frame(victim, frame) {
+                Ok(()) => {
+                    if self.cache.remove_victim(victim).is_some() {
+                        return Ok(());
+                    }
+                }
+                Err(Error::PagePinned(_)) => self.cache.keep_victim(victim),
+                Err(e) => {
+                    println!("[DEBUG][DiskManager] keeping page {victim} cached: {e}");
+                    self.cache.keep_victim(victim);
+                    return Err(self.read_only().map_or(e, Error::ReadOnly));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // read only, dirty frames can not be written back and only
+    // clean ones are evicted
+    fn select_victim(&mut self) -> Result<Option<PageID>> {
+        let read_only = self.read_only();
+        match self.cache.select_victim(read_only.is_some()) {
+            Ok(victim) => Ok(victim),
+            Err(e) => Err(read_only.map_or(e.into(), Error::ReadOnly)),
+        }
+    }
+
+    fn load_frame(&mut self, page_id: PageID) -> Result<Arc<RwLock<Frame>>> {
+        self.ensure_open()?;
         if let Some(frame) = self.cache.lookup_frame(page_id) {
-            println!(
-                "[DEBUG][DiskManager][Cache] from cache page {}",
-                frame.read().unwrap()
-            );
-            return Some(frame);
+            println!("[DEBUG][DiskManager][Cache] from cache page {page_id}");
+            return Ok(frame);
         }
 
         println!("[DEBUG][DiskManager] fetching from disk for {}", page_id);
         if let Some(offset) = self.page_directory.query_page(page_id) {
-            let mut reader = BufReader::new(&self.db_file);
-            reader.seek(SeekFrom::Start(offset as u64)).unwrap();
-
-            let mut content: [u8; FRAME_SIZE as usize] = [0; FRAME_SIZE as usize];
-            reader
-                .read_exact(&mut content)
-                .unwrap_or_else(|_| panic!("failed to read {FRAME_SIZE} from {offset}"));
+            self.make_room()?;
+            let buffer = self.cache.frame_buffer()?;
+            let (header, content) = self.scheduler.read(page_id, offset, buffer).wait()?;
+            verify_page(page_id, offset, &header, &content)?;
 
             println!("[DEBUG][DiskManager] fetched from disk");
 
             println!("[DEBUG][DiskManager] updating cache");
-            let evict = self.cache.put_frame(page_id, offset, Box::new(content));
-            if let Some(frame) = evict {
-                println!("flushing frame {}", frame.read().unwrap().page_id);
-                let _ = self.flush_frame(frame);
-            }
+            self.cache
+                .put_frame(page_id, offset, header.page_lsn, content)?;
 
-            return self.cache.lookup_frame(page_id);
+            self.cache
+                .lookup_frame(page_id)
+                .ok_or(Error::PageNotFound(page_id))
         } else {
             println!("frame not available on disk");
-            return None;
+            Err(Error::PageNotFound(page_id))
+        }
+    }
+
+    /// Log the changes to pages are appended to
+    pub fn wal(&self) -> Arc<LogManager> {
+        Arc::clone(&self.wal)
+    }
+
+    /// Number of write backs skipped because the frame was clean
+    pub fn avoided_writes(&self) -> u64 {
+        self.avoided_writes
+    }
+
+    /// Handle to the scheduler, so page I/O can be issued without
+    /// holding the DiskManager
+    pub fn scheduler(&self) -> Arc<DiskScheduler> {
+        Arc::clone(&self.scheduler)
+    }
+
+    pub fn get_db_size(&self) -> Result<u64> {
+        Ok(self.backend.len()?)
+    }
+}
+
+impl Drop for DiskManager {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            println!("[DEBUG][DiskManager] failed to close cleanly: {e}");
+        }
+    }
+}
+
+// a page as it is stored in the db file, header followed by content
+fn encode_page(
+    header: &PageHeader,
+    content: &[u8; FRAME_SIZE as usize],
+) -> [u8; PAGE_SIZE as usize] {
+    let mut bytes = [0; PAGE_SIZE as usize];
+    bytes[..PAGE_HEADER_SIZE as usize].copy_from_slice(&header.encode());
+    bytes[PAGE_HEADER_SIZE as usize..].copy_from_slice(content);
+    bytes
+}
+
+fn decode_header(page: &[u8; PAGE_SIZE as usize]) -> PageHeader {
+    PageHeader::decode(page[..PAGE_HEADER_SIZE as usize].try_into().unwrap())
+}
+
+// the sidecar of the db file with the extension, created when missing
+fn sidecar(backend: &dyn StorageBackend, extension: &str) -> io::Result<Arc<dyn StorageBackend>> {
+    backend.sidecar(extension, true)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("backend has no .{extension} sidecar"),
+        )
+    })
+}
+
+// page id every slot in use is mapped to, the persisted directory
+// along with the pages created and deleted since, which are only
+// in the log until the directory is persisted again
+fn mapped_offsets(
+    page_directory: &PageDirector,
+    wal: &LogManager,
+    directory_lsn: Lsn,
+) -> Result<HashMap<usize, PageID>> {
+    let mut pages: HashMap<PageID, usize> = page_directory.pages().collect();
+    if !wal.is_empty() {
+        for record in wal.records_from(directory_lsn.max(wal.base_lsn()))? {
+            match record.body {
+                LogBody::NewPage { page_id, offset } => {
+                    pages.insert(page_id, offset);
+                }
+                LogBody::DeletePage { page_id } => {
+                    pages.remove(&page_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(pages
+        .into_iter()
+        .map(|(page_id, offset)| (offset, page_id))
+        .collect())
+}
+
+// pages read from disk are checked against the checksum written
+// along with them
+fn verify_page(
+    page_id: PageID,
+    offset: usize,
+    header: &PageHeader,
+    content: &[u8; FRAME_SIZE as usize],
+) -> Result<()> {
+    if header.verify(content) {
+        Ok(())
+    } else {
+        Err(Error::ChecksumMismatch { page_id, offset })
+    }
+}
+
+// bytes of a page content accessed at `offset`, fails if they
+// do not fit in the page
+pub(crate) fn page_range(page_id: PageID, offset: usize, len: usize) -> Result<Range<usize>> {
+    match offset.checked_add(len) {
+        Some(end) if end <= FRAME_SIZE as usize => Ok(offset..end),
+        _ => Err(Error::OutOfPage {
+            page_id,
+            offset,
+            len,
+        }),
+    }
+}
+
+// write errors that keep happening until someone intervenes,
+// e.g. by freeing up disk space
+fn is_persistent(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::StorageFull
+            | io::ErrorKind::QuotaExceeded
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::FileTooLarge
+    )
+}
+
+// a page as stored on disk, its header and the frame buffer it
+// was read into
+pub type DiskPage = (PageHeader, FrameBuf);
+
+/// Result of a disk request, the page read for reads
+pub type DiskResult<T = DiskPage> = io::Result<T>;
+
+// number of I/O worker threads per DiskScheduler
+pub const DISK_WORKERS: usize = 2;
+
+pub enum DiskRequest {
+    Read(ReadRequest),
+    Write(WriteRequest),
+}
+
+impl DiskRequest {
+    fn page_id(&self) -> PageID {
+        match self {
+            DiskRequest::Read(read) => read.page_id,
+            DiskRequest::Write(write) => write.page_id,
+        }
+    }
+}
+
+pub struct ReadRequest {
+    pub page_id: PageID,
+    pub offset: usize,
+    // the whole page is read into it, header included
+    pub buffer: FrameBuf,
+    // fulfilled by the worker once the request is done
+    pub completion: oneshot::Sender<DiskResult>,
+}
+
+pub struct WriteRequest {
+    pub page_id: PageID,
+    pub offset: usize,
+    pub page: PageImage,
+    // fulfilled by the worker once the request is done
+    pub completion: oneshot::Sender<DiskResult<()>>,
+}
+
+/// A page to write as it is stored on disk, header included
+pub enum PageImage {
+    // the frame itself, its header encoded in front of its content.
+    // it stays latched for reads until the write completed
+    Frame(ArcRwLockReadGuard<RawRwLock, Frame>),
+    // a copy of the page, taken by whoever holds the frame latch
+    Snapshot(Box<[u8; PAGE_SIZE as usize]>),
+}
+
+impl PageImage {
+    /// Copy of a frame, taken by whoever holds its latch
+    pub fn snapshot(frame: &Frame) -> PageImage {
+        let header = PageHeader::new(frame.page_lsn, &frame.content);
+        PageImage::Snapshot(Box::new(encode_page(&header, &frame.content)))
+    }
+
+    pub fn bytes(&self) -> &[u8; PAGE_SIZE as usize] {
+        match self {
+            PageImage::Frame(frame) => frame.content.page(),
+            PageImage::Snapshot(page) => page,
+        }
+    }
+
+    pub fn header(&self) -> PageHeader {
+        decode_header(self.bytes())
+    }
+}
+
+/// Waits for the completion of a scheduled `DiskRequest`
+pub struct DiskFuture<T = DiskPage> {
+    receiver: oneshot::Receiver<DiskResult<T>>,
+}
+
+impl<T> DiskFuture<T> {
+    /// Blocks the calling thread until the request completes. Must
+    /// not be called from within an async runtime, `.await` the
+    /// future there instead
+    pub fn wait(self) -> DiskResult<T> {
+        self.receiver
+            .blocking_recv()
+            .unwrap_or_else(|_| Err(io::Error::other("disk scheduler shut down")))
+    }
+}
+
+impl<T> Future for DiskFuture<T> {
+    type Output = DiskResult<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<DiskResult<T>> {
+        Pin::new(&mut self.receiver).poll(cx).map(|result| {
+            result.unwrap_or_else(|_| Err(io::Error::other("disk scheduler shut down")))
+        })
+    }
+}
+
+/// Executes page reads and writes on dedicated worker threads. Each
+/// worker has its own queue and requests are routed by page id, so
+/// requests for the same page complete in the order they were
+/// scheduled
+pub struct DiskScheduler {
+    db_file: Arc<dyn StorageBackend>,
+    queues: Vec<mpsc::Sender<DiskRequest>>,
+    workers: Vec<JoinHandle<()>>,
+    // shared with the workers, read before every round of requests
+    options: Arc<RwLock<WriteOptions>>,
+    // set by the workers when a page write fails in a way retrying
+    // does not fix, e.g. because the disk is full
+    write_failure: Arc<WriteFailure>,
+    // bumped every time a write of the page is scheduled, lets an
+    // async read detect that it raced with a write back
+    write_epochs: Mutex<HashMap<PageID, u64>>,
+}
+
+type WriteFailure = RwLock<Option<io::ErrorKind>>;
+
+// how the workers write pages, can be changed while they run
+#[derive(Debug, Default, Clone)]
+struct WriteOptions {
+    // page writes go through it first when set
+    double_write: Option<Arc<DoubleWriteBuffer>>,
+    // handle page writes go through instead of the shared one,
+    // the db file opened with O_DSYNC
+    write_file: Option<Arc<dyn StorageBackend>>,
+    // every page write is followed by an fdatasync
+    sync_writes: bool,
+}
+
+impl DiskScheduler {
+    pub fn new(db_file: Arc<dyn StorageBackend>, worker_count: usize) -> DiskScheduler {
+        DiskScheduler::spawn(db_file, worker_count, None)
+    }
+
+    /// Like `DiskScheduler::new`, completed page writes are reported
+    /// to the log so it knows which pages are still dirty
+    pub fn with_log(
+        db_file: Arc<dyn StorageBackend>,
+        worker_count: usize,
+        wal: Arc<LogManager>,
+    ) -> DiskScheduler {
+        DiskScheduler::spawn(db_file, worker_count, Some(wal))
+    }
+
+    fn spawn(
+        db_file: Arc<dyn StorageBackend>,
+        worker_count: usize,
+        wal: Option<Arc<LogManager>>,
+    ) -> DiskScheduler {
+        assert!(worker_count > 0, "DiskScheduler needs at least one worker");
+
+        let mut queues = Vec::with_capacity(worker_count);
+        let mut workers = Vec::with_capacity(worker_count);
+        let options: Arc<RwLock<WriteOptions>> = Arc::default();
+        let write_failure: Arc<WriteFailure> = Arc::default();
+
+        for worker in 0..worker_count {
+            let (sender, receiver) = mpsc::channel::<DiskRequest>();
+            let db_file = Arc::clone(&db_file);
+            let wal = wal.clone();
+            let options = Arc::clone(&options);
+            let write_failure = Arc::clone(&write_failure);
+
+            let handle = thread::Builder::new()
+                .name(format!("forklift-disk-{worker}"))
+                .spawn(move || {
+                    // runs until every sender is dropped. requests queued
+                    // behind the first one are handled along with it, so
+                    // writes can be batched in the double write buffer
+                    while let Ok(request) = receiver.recv() {
+                        let mut requests = vec![request];
+                        requests.extend(receiver.try_iter());
+
+                        let options = options.read().clone();
+                        let wal = wal.as_deref();
+                        DiskScheduler::run(
+                            &*db_file,
+                            wal,
+                            &write_failure,
+                            &options,
+                            worker,
+                            requests,
+                        );
+                    }
+                })
+                .expect("failed to spawn disk worker");
+
+            queues.push(sender);
+            workers.push(handle);
+        }
+
+        DiskScheduler {
+            db_file,
+            queues,
+            workers,
+            options,
+            write_failure,
+            write_epochs: Mutex::default(),
         }
     }
 
-    pub fn get_db_size(&self) -> u64 {
-        self.db_file
-            .metadata()
-            .expect("failed to read metadata for filesize")
-            .len()
+    /// Kind of the error a page write failed with, when it is one that
+    /// retrying does not fix. Cleared with `DiskScheduler::clear_write_failure`
+    pub fn write_failure(&self) -> Option<io::ErrorKind> {
+        *self.write_failure.read()
+    }
+
+    pub fn clear_write_failure(&self) {
+        *self.write_failure.write() = None;
+    }
+
+    /// Sends every following page write through the double write
+    /// buffer, or straight to the db file again when `None`
+    pub fn set_double_write(&self, buffer: Option<Arc<DoubleWriteBuffer>>) {
+        self.options.write().double_write = buffer;
+    }
+
+    pub fn double_write_enabled(&self) -> bool {
+        self.options.read().double_write.is_some()
+    }
+
+    /// Writes pages through `file` instead of the handle the scheduler
+    /// was created with, used to write through a handle opened with
+    /// O_DSYNC. Reads keep using the original handle
+    pub fn set_write_file(&self, file: Option<Arc<dyn StorageBackend>>) {
+        self.options.write().write_file = file;
+    }
+
+    /// Whether every page write is followed by an fdatasync
+    pub fn set_sync_writes(&self, sync_writes: bool) {
+        self.options.write().sync_writes = sync_writes;
+    }
+
+    /// Flushes every completed page write to stable storage
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.db_file.sync()
+    }
+
+    // executes the requests in order. with a double write buffer the
+    // writes between two reads are written as batches, a read still
+    // sees every write queued before it
+    fn run(
+        db_file: &dyn StorageBackend,
+        wal: Option<&LogManager>,
+        failure: &WriteFailure,
+        options: &WriteOptions,
+        region: usize,
+        requests: Vec<DiskRequest>,
+    ) {
+        let write_file = options.write_file.as_deref().unwrap_or(db_file);
+        let mut batch = vec![];
+        for request in requests {
+            let write = match request {
+                DiskRequest::Write(write) => write,
+                DiskRequest::Read(read) => {
+                    if let Some(buffer) = &options.double_write {
+                        let batch = mem::take(&mut batch);
+                        DiskScheduler::write_batch(write_file, wal, failure, buffer, region, batch);
+                    }
+                    let _ = read.completion.send(DiskScheduler::read_page(
+                        db_file,
+                        read.offset,
+                        read.buffer,
+                    ));
+                    continue;
+                }
+            };
+
+            if let Some(buffer) = &options.double_write {
+                batch.push(write);
+                if batch.len() == DOUBLE_WRITE_BATCH {
+                    let batch = mem::take(&mut batch);
+                    DiskScheduler::write_batch(write_file, wal, failure, buffer, region, batch);
+                }
+                continue;
+            }
+
+            // header and content go through a single call, so the page
+            // is written with one request to the OS
+            let mut result = write_file.write_at(write.page.bytes(), write.offset as u64);
+            if options.sync_writes {
+                result = result.and_then(|_| write_file.sync());
+            }
+            DiskScheduler::complete(wal, failure, write, result);
+        }
+
+        if let Some(buffer) = &options.double_write {
+            DiskScheduler::write_batch(write_file, wal, failure, buffer, region, batch);
+        }
+    }
+
+    // the batch is made durable in the double write buffer before any
+    // page of it is written in place, the db file is synced afterwards
+    // so the region can be reused by the next batch
+    fn write_batch(
+        db_file: &dyn StorageBackend,
+        wal: Option<&LogManager>,
+        failure: &WriteFailure,
+        buffer: &DoubleWriteBuffer,
+        region: usize,
+        batch: Vec<WriteRequest>,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+
+        let pages: Vec<DoubleWritePage> = batch
+            .iter()
+            .map(|write| DoubleWritePage {
+                page_id: write.page_id,
+                offset: write.offset,
+                page_lsn: write.page.header().page_lsn,
+                page: write.page.bytes(),
+            })
+            .collect();
+        let result = buffer.write_batch(region, &pages).and_then(|_| {
+            for page in pages.iter() {
+                db_file.write_at(page.page, page.offset as u64)?;
+            }
+            db_file.sync()
+        });
+        drop(pages);
+
+        for write in batch {
+            let result = match &result {
+                Ok(()) => Ok(()),
+                Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+            };
+            DiskScheduler::complete(wal, failure, write, result);
+        }
+    }
+
+    // hands the result to the caller, a completed write is reported
+    // to the log and a write failing for good is recorded
+    fn complete(
+        wal: Option<&LogManager>,
+        failure: &WriteFailure,
+        write: WriteRequest,
+        result: DiskResult<()>,
+    ) {
+        let WriteRequest {
+            page_id,
+            page,
+            completion,
+            ..
+        } = write;
+        let page_lsn = page.header().page_lsn;
+        // the frame is released before the caller learns about the
+        // write, so it can be latched again right away
+        drop(page);
+
+        match &result {
+            Ok(()) => {
+                if let Some(wal) = wal {
+                    wal.page_written(page_id, page_lsn);
+                }
+            }
+            Err(e) if is_persistent(e.kind()) => {
+                println!("[DEBUG][DiskScheduler] write of page {page_id} failed for good: {e}");
+                *failure.write() = Some(e.kind());
+            }
+            Err(_) => {}
+        }
+        // the caller may have stopped waiting
+        let _ = completion.send(result);
+    }
+
+    /// Creates the completion side of a request together with
+    /// the future waiting on it
+    pub fn create_promise<T>() -> (oneshot::Sender<DiskResult<T>>, DiskFuture<T>) {
+        let (sender, receiver) = oneshot::channel();
+        (sender, DiskFuture { receiver })
+    }
+
+    /// Queues a request on the worker owning its page
+    pub fn schedule(&self, request: DiskRequest) {
+        let worker = request.page_id() as usize % self.queues.len();
+        if let Err(mpsc::SendError(request)) = self.queues[worker].send(request) {
+            let stopped = || io::Error::other("disk worker stopped");
+            match request {
+                DiskRequest::Read(read) => {
+                    let _ = read.completion.send(Err(stopped()));
+                }
+                DiskRequest::Write(write) => {
+                    let _ = write.completion.send(Err(stopped()));
+                }
+            }
+        }
+    }
+
+    /// Number of writes of the page scheduled so far
+    pub fn write_epoch(&self, page_id: PageID) -> u64 {
+        self.write_epochs.lock().get(&page_id).copied().unwrap_or(0)
+    }
+
+    /// Reads a page into `buffer`, which is handed back with the
+    /// page header once the read completed
+    pub fn read(&self, page_id: PageID, offset: usize, buffer: FrameBuf) -> DiskFuture {
+        let (completion, future) = DiskScheduler::create_promise();
+        self.schedule(DiskRequest::Read(ReadRequest {
+            page_id,
+            offset,
+            buffer,
+            completion,
+        }));
+        future
+    }
+
+    pub fn write(&self, page_id: PageID, offset: usize, page: PageImage) -> DiskFuture<()> {
+        *self.write_epochs.lock().entry(page_id).or_insert(0) += 1;
+        let (completion, future) = DiskScheduler::create_promise();
+        self.schedule(DiskRequest::Write(WriteRequest {
+            page_id,
+            offset,
+            page,
+            completion,
+        }));
+        future
+    }
+
+    // header and content are read with a single call
+    fn read_page(db_file: &dyn StorageBackend, offset: usize, mut buffer: FrameBuf) -> DiskResult {
+        db_file.read_at(buffer.page_mut(), offset as u64)?;
+        Ok((decode_header(buffer.page()), buffer))
+    }
+}
+
+impl Drop for DiskScheduler {
+    fn drop(&mut self) {
+        // closing the queues lets the workers drain and exit
+        self.queues.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
     }
 }
 
-pub struct DiskScheduler {}
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path, sync::Arc};
+
+    use parking_lot::RwLock;
+
+    use crate::{
+        buffer::arena::FrameArena,
+        storage::{
+            doublewrite::{DoubleWriteBuffer, DOUBLE_WRITE_EXTENSION},
+            file::{FileBackend, StorageBackend},
+            page::{Frame, PageHeader, FRAME_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE},
+        },
+    };
+
+    use super::{encode_page, DiskScheduler, PageImage};
+
+    #[test]
+    fn test_scheduler_read_write() {
+        scheduler_read_write("/tmp/test_scheduler_read_write.db", false);
+    }
+
+    #[test]
+    fn test_scheduler_read_write_double_write() {
+        scheduler_read_write("/tmp/test_scheduler_read_write_double_write.db", true);
+    }
+
+    fn scheduler_read_write(file_path: &str, double_write: bool) {
+        let _ = fs::remove_file(file_path);
 
-impl DiskScheduler {}
+        let file = FileBackend::create(Path::new(file_path)).unwrap();
+        file.set_len(PAGE_SIZE * 4).unwrap();
+
+        let buffer_path = DoubleWriteBuffer::path(file_path);
+        let buffer = double_write.then(|| {
+            let buffer_file = file.sidecar(DOUBLE_WRITE_EXTENSION, true).unwrap().unwrap();
+            Arc::new(DoubleWriteBuffer::create(buffer_file, 2).unwrap())
+        });
+        let scheduler = DiskScheduler::new(Arc::new(file), 2);
+        scheduler.set_double_write(buffer);
+
+        // queue everything before waiting on anything, writes to the
+        // same page must land in the order they were scheduled
+        let mut pending = vec![];
+        for page_id in 0..4u32 {
+            for round in 0..3u8 {
+                let header = PageHeader {
+                    page_lsn: round as u64,
+                    checksum: 0,
+                };
+                let content = [page_id as u8 * 10 + round; FRAME_SIZE as usize];
+                pending.push(scheduler.write(
+                    page_id,
+                    page_id as usize * PAGE_SIZE as usize,
+                    PageImage::Snapshot(Box::new(encode_page(&header, &content))),
+                ));
+            }
+        }
+        // pages are read straight into the buffers of an arena
+        let arena = FrameArena::new(4, Arc::default());
+        let reads: Vec<_> = (0..4u32)
+            .map(|page_id| {
+                let buffer = arena.alloc().unwrap();
+                scheduler.read(page_id, page_id as usize * PAGE_SIZE as usize, buffer)
+            })
+            .collect();
+
+        for future in pending {
+            future.wait().unwrap();
+        }
+        for (page_id, future) in reads.into_iter().enumerate() {
+            let (header, content) = future.wait().unwrap();
+            assert_eq!(header.page_lsn, 2);
+            assert!(content.in_arena());
+            assert!(content.iter().all(|byte| *byte == page_id as u8 * 10 + 2));
+        }
+        assert_eq!(arena.available(), 4);
+
+        // a frame is written from its own memory and stays latched
+        // for reads until the write completed
+        let mut frame = Frame::new(1, PAGE_SIZE as usize, arena.alloc().unwrap());
+        frame.content.fill(42);
+        let header = PageHeader::new(7, &frame.content);
+        frame.content.page_mut()[..PAGE_HEADER_SIZE as usize].copy_from_slice(&header.encode());
+        let frame = Arc::new(RwLock::new(frame));
+        let write = scheduler.write(1, PAGE_SIZE as usize, PageImage::Frame(frame.read_arc()));
+        write.wait().unwrap();
+        assert!(frame.try_write().is_some());
+
+        let buffer = arena.alloc().unwrap();
+        let (header, content) = scheduler
+            .read(1, PAGE_SIZE as usize, buffer)
+            .wait()
+            .unwrap();
+        assert_eq!(header.page_lsn, 7);
+        assert!(header.verify(&content));
+
+        // reading past the end of the file fails instead of panicking
+        let buffer = arena.alloc().unwrap();
+        assert!(scheduler
+            .read(9, PAGE_SIZE as usize * 9, buffer)
+            .wait()
+            .is_err());
+
+        drop(scheduler);
+        fs::remove_file(file_path).unwrap();
+        if double_write {
+            fs::remove_file(buffer_path).unwrap();
+        }
+    }
+}
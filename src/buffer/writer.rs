@@ -0,0 +1,99 @@
+// background writer trickling dirty pages to disk
+//
+// every round the writer looks at the unpinned frames the
+// replacer would evict next and writes back the dirty ones,
+// so a foreground fetch evicting them later finds them clean
+// and does not pay for a synchronous write. the disk manager
+// is only held while the writes are scheduled
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::scheduler::DiskManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterConfig {
+    // time between two rounds
+    pub interval: Duration,
+    // frames looked at, and at most written, per round
+    pub max_pages: usize,
+}
+
+impl Default for WriterConfig {
+    fn default() -> WriterConfig {
+        WriterConfig {
+            interval: Duration::from_millis(200),
+            max_pages: 16,
+        }
+    }
+}
+
+pub struct BackgroundWriter {
+    // dropping the sender wakes the thread up and stops it
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+    pages_written: Arc<AtomicU64>,
+}
+
+impl BackgroundWriter {
+    pub fn start(disk_manager: Arc<Mutex<DiskManager>>, config: WriterConfig) -> BackgroundWriter {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let pages_written = Arc::new(AtomicU64::new(0));
+
+        let written = Arc::clone(&pages_written);
+        let handle = thread::spawn(move || loop {
+            match stopped.recv_timeout(config.interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+
+            let scheduled = disk_manager
+                .lock()
+                .unwrap()
+                .schedule_background_flush(config.max_pages);
+
+            // a failed write leaves the frame dirty and cached, it
+            // is written again by a later round or when evicted
+            for flush in scheduled {
+                match flush.wait() {
+                    Ok(()) => {
+                        written.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e) => println!("[DEBUG][WRITER] write back failed: {e}"),
+                }
+            }
+        });
+
+        BackgroundWriter {
+            stop: Some(stop),
+            handle: Some(handle),
+            pages_written,
+        }
+    }
+
+    /// Number of pages written back by the writer so far
+    pub fn pages_written(&self) -> u64 {
+        self.pages_written.load(Ordering::Relaxed)
+    }
+
+    /// Stops the writer and waits for its current round to finish
+    pub fn stop(&mut self) {
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("background writer panicked");
+        }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
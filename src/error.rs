@@ -0,0 +1,150 @@
+// this file defines the error returned by the public api of
+// the disk manager and the buffer pool managers
+//
+// lower level modules keep their own error enums describing
+// a failure in detail, they are converted into `Error` once
+// they reach the disk manager so callers match on a single
+// type and can tell failures they can recover from apart
+
+use std::{fmt, io};
+
+use crate::{
+    buffer::cache,
+    storage::{directory, header, page::PageID},
+    wal,
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    PageNotFound(PageID),
+    // the page exists but has no frame in the cache
+    PageNotCached(PageID),
+    // every frame in the cache is pinned
+    NoFreeFrames,
+    PagePinned(PageID),
+    // the bytes accessed do not fit in the page content
+    OutOfPage {
+        page_id: PageID,
+        offset: usize,
+        len: usize,
+    },
+    // the page read does not match the checksum in its header,
+    // directory pages are not mapped and report page id 0
+    ChecksumMismatch {
+        page_id: PageID,
+        offset: usize,
+    },
+    // the db file or the log holds data that can not be decoded
+    Corruption(String),
+    // the file is not a database this version can open
+    InvalidFile(header::Error),
+    Log(wal::Error),
+    // the disk manager was closed, it has to be reopened
+    Closed,
+    // a write back failed in a way retrying does not fix, pages
+    // can be read but not changed until writes are resumed
+    ReadOnly(io::ErrorKind),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::PageNotFound(page_id) => write!(f, "Page {page_id} does not exist"),
+            Self::PageNotCached(page_id) => write!(f, "Page {page_id} is not in the cache"),
+            Self::NoFreeFrames => write!(f, "every frame in the cache is pinned"),
+            Self::PagePinned(page_id) => write!(f, "page {page_id} is pinned"),
+            Self::OutOfPage {
+                page_id,
+                offset,
+                len,
+            } => write!(
+                f,
+                "{len} bytes at offset {offset} do not fit in page {page_id}"
+            ),
+            Self::ChecksumMismatch { page_id, offset } => write!(
+                f,
+                "Page {page_id} at offset {offset} does not match its checksum, the page is corrupt"
+            ),
+            Self::Corruption(reason) => write!(f, "database is corrupt: {reason}"),
+            Self::InvalidFile(e) => write!(f, "{e}"),
+            Self::Log(e) => write!(f, "{e}"),
+            Self::Closed => write!(f, "disk manager is closed"),
+            Self::ReadOnly(kind) => write!(
+                f,
+                "buffer pool is read only after a page write failed: {kind}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidFile(e) => Some(e),
+            Self::Log(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Error {
+        Error::Corruption(e.to_string())
+    }
+}
+
+impl From<cache::Error> for Error {
+    fn from(e: cache::Error) -> Error {
+        match e {
+            cache::Error::NoFreeFrames => Error::NoFreeFrames,
+            cache::Error::FramePinned(page_id) => Error::PagePinned(page_id),
+        }
+    }
+}
+
+impl From<directory::Error> for Error {
+    fn from(e: directory::Error) -> Error {
+        match e {
+            directory::Error::PageNotFound(page_id) => Error::PageNotFound(page_id),
+            directory::Error::DecodeDirectoryError => Error::Corruption(e.to_string()),
+        }
+    }
+}
+
+impl From<header::Error> for Error {
+    fn from(e: header::Error) -> Error {
+        Error::InvalidFile(e)
+    }
+}
+
+impl From<wal::Error> for Error {
+    fn from(e: wal::Error) -> Error {
+        Error::Log(e)
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(e: tokio::task::JoinError) -> Error {
+        Error::Io(io::Error::other(e))
+    }
+}
@@ -1,4 +1,8 @@
 // Forklift
 // A buffer-pool manager
 pub mod buffer;
+pub mod error;
 pub mod storage;
+pub mod wal;
+
+pub use error::{Error, Result};
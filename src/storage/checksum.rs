@@ -0,0 +1,61 @@
+// CRC32C (Castagnoli) checksum stored in every page header
+//
+// a plain table driven implementation processing a byte at
+// a time, the table is built at compile time. it is only used
+// to detect pages that were corrupted or torn on disk
+
+// reversed representation of the polynomial 0x1EDC6F41
+const POLYNOMIAL: u32 = 0x82F6_3B78;
+
+const TABLE: [u32; 256] = build_table();
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+/// Checksum of `bytes`
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    crc32c_append(0, bytes)
+}
+
+/// Extends the checksum `crc` of some bytes with the bytes that
+/// follow them
+pub fn crc32c_append(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes {
+        crc = TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crc32c, crc32c_append};
+
+    #[test]
+    fn test_crc32c_known_values() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(&[0; 32]), 0x8A91_36AA);
+        assert_eq!(
+            crc32c_append(crc32c(b"1234"), b"56789"),
+            crc32c(b"123456789")
+        );
+    }
+}
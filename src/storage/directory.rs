@@ -1,8 +1,22 @@
 use std::{collections::HashMap, fmt, vec};
 
-use super::page::{PageID, FRAME_SIZE};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+use super::{
+    header::HEADER_SIZE,
+    page::{PageID, FRAME_SIZE, PAGE_SIZE},
+};
+
+// each directory page starts with the offset of the next
+// directory page (0 ends the chain) followed by the number
+// of payload bytes held in the page
+const DIRECTORY_PAGE_HEADER: usize = 12;
+pub const DIRECTORY_PAGE_CAPACITY: usize = FRAME_SIZE as usize - DIRECTORY_PAGE_HEADER;
+
+// (offset, content) of an encoded directory page
+pub type DirectoryPage = (usize, Box<[u8; FRAME_SIZE as usize]>);
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PageDirector {
     // HashMap<PageID, usize>
     // @ PageID     : page id
@@ -12,18 +26,28 @@ pub struct PageDirector {
     map: HashMap<PageID, usize>,
     free_slots: Vec<usize>,
     highest_page_id: PageID,
+    // offset right past the last slot handed out, where
+    // the next slot goes when there are no free slots
+    next_offset: usize,
 }
 
 #[derive(Debug, Clone)]
 pub enum Error {
-    DeleteFromDirectoryError,
+    PageNotFound(PageID),
+    DecodeDirectoryError,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            Self::DeleteFromDirectoryError => {
-                write!(f, "failed delete from Page Directory, missing pageid")
+            Self::PageNotFound(page_id) => {
+                write!(
+                    f,
+                    "failed delete from Page Directory, missing page {page_id}"
+                )
+            }
+            Self::DecodeDirectoryError => {
+                write!(f, "failed to decode Page Directory, file is corrupt")
             }
         }
     }
@@ -37,6 +61,7 @@ impl PageDirector {
             map: HashMap::with_capacity(10),
             free_slots: vec![],
             highest_page_id: 0,
+            next_offset: HEADER_SIZE as usize,
         }
     }
 
@@ -56,53 +81,127 @@ impl PageDirector {
         self.map.get(&page_id).copied()
     }
 
-    pub fn register_new_page(&mut self) -> (PageID, usize) {
-        if self.free_slots.is_empty() && self.map.is_empty() {
-            // no page exists in the page directory
-            self.highest_page_id += 1;
-            self.map.insert(self.highest_page_id, 0);
-            return (self.highest_page_id, 0);
+    /// Every registered page along with the offset of its slot
+    pub fn pages(&self) -> impl Iterator<Item = (PageID, usize)> + '_ {
+        self.map.iter().map(|(page_id, offset)| (*page_id, *offset))
+    }
+
+    /// Maps a new page id to a slot, returns the page id, the offset
+    /// of its slot and whether the slot was freed by a deleted page
+    pub fn register_new_page(&mut self) -> (PageID, usize, bool) {
+        self.highest_page_id += 1;
+        let reused = !self.free_slots.is_empty();
+        let offset = self.reserve_slot();
+        self.map.insert(self.highest_page_id, offset);
+
+        (self.highest_page_id, offset, reused)
+    }
+
+    /// Maps a page to the slot it was registered with before a crash,
+    /// used when the registration is replayed from the log. Replaying
+    /// a registration the directory already holds does nothing
+    pub fn restore_page(&mut self, page_id: PageID, offset: usize) {
+        if self.map.get(&page_id) == Some(&offset) {
+            return;
         }
 
-        if !self.map.is_empty() && !self.free_slots.is_empty() {
-            self.highest_page_id += 1;
-            let offset = self
-                .free_slots
-                .pop()
-                .expect("failed to pop from free slots vec");
-            self.map.insert(self.highest_page_id, offset);
+        self.free_slots.retain(|free| *free != offset);
+        self.next_offset = self.next_offset.max(offset + PAGE_SIZE as usize);
+        self.highest_page_id = self.highest_page_id.max(page_id);
+        self.map.insert(page_id, offset);
+    }
 
+    /// Hands out the offset of a slot that is not mapped to any
+    /// page id, preferring free slots over growing the file
+    pub fn reserve_slot(&mut self) -> usize {
+        if let Some(offset) = self.free_slots.pop() {
             println!(
                 "[DEBUG][PageDirectory] using offset {} from available free slots",
                 offset
             );
-            return (self.highest_page_id, offset);
+            return offset;
         }
 
-        if !self.map.is_empty() && self.free_slots.is_empty() {
-            let map_clone = self.map.clone();
-            let max_element = map_clone.iter().max_by_key(|entry| entry.1).unwrap();
-            self.highest_page_id += 1;
-            self.map
-                .insert(self.highest_page_id, max_element.1 + FRAME_SIZE as usize);
-            return (
-                self.highest_page_id,
-                max_element.1.to_owned() + FRAME_SIZE as usize,
-            );
-        }
+        let offset = self.next_offset;
+        self.next_offset += PAGE_SIZE as usize;
+        offset
+    }
 
-        (u32::MAX, 0) // fail condition for now
+    /// Returns a slot taken with `PageDirector::reserve_slot`
+    pub fn release_slot(&mut self, offset: usize) {
+        self.free_slots.push(offset);
     }
 
-    pub fn remove_page(&mut self, page_id: PageID) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn remove_page(&mut self, page_id: PageID) -> Result<(), Error> {
         if let Some((_, offset)) = self.map.remove_entry(&page_id) {
             self.free_slots.push(offset);
             dbg!(&self.free_slots);
             Ok(())
         } else {
-            Err(Box::new(Error::DeleteFromDirectoryError))
+            Err(Error::PageNotFound(page_id))
         }
     }
+
+    /// Serializes the directory into a chain of directory pages,
+    /// returned in chain order
+    ///
+    /// slots for the new chain are reserved before the slots of
+    /// `old_pages` are released, so the previous chain stays intact
+    /// until the file header is pointed at the new one
+    pub fn encode_pages(&mut self, old_pages: &[usize]) -> crate::Result<Vec<DirectoryPage>> {
+        // integers are fixed width, so the encoded size only depends on
+        // the number of entries. reserving slots can only shrink the free
+        // list while releasing the old chain grows it
+        let upper_bound =
+            bincode::serialized_size(self)? as usize + old_pages.len() * std::mem::size_of::<u64>();
+        let page_count = upper_bound.div_ceil(DIRECTORY_PAGE_CAPACITY);
+
+        let offsets: Vec<usize> = (0..page_count).map(|_| self.reserve_slot()).collect();
+        for offset in old_pages {
+            self.release_slot(*offset);
+        }
+
+        let bytes = bincode::serialize(self)?;
+        let mut chunks = bytes.chunks(DIRECTORY_PAGE_CAPACITY);
+
+        let mut pages = Vec::with_capacity(page_count);
+        for (idx, offset) in offsets.iter().enumerate() {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let next = offsets.get(idx + 1).copied().unwrap_or(0) as u64;
+
+            let mut content = Box::new([0; FRAME_SIZE as usize]);
+            content[0..8].copy_from_slice(&next.to_le_bytes());
+            content[8..12].copy_from_slice(&(chunk.len() as u32).to_le_bytes());
+            content[DIRECTORY_PAGE_HEADER..DIRECTORY_PAGE_HEADER + chunk.len()]
+                .copy_from_slice(chunk);
+
+            pages.push((*offset, content));
+        }
+
+        Ok(pages)
+    }
+
+    /// Splits a directory page into the offset of the next page in
+    /// the chain (0 at the end) and its payload
+    pub fn decode_page(content: &[u8; FRAME_SIZE as usize]) -> Result<(usize, &[u8]), Error> {
+        let next = u64::from_le_bytes(content[0..8].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(content[8..12].try_into().unwrap()) as usize;
+
+        if len > DIRECTORY_PAGE_CAPACITY {
+            return Err(Error::DecodeDirectoryError);
+        }
+
+        Ok((
+            next,
+            &content[DIRECTORY_PAGE_HEADER..DIRECTORY_PAGE_HEADER + len],
+        ))
+    }
+
+    /// Rebuilds a directory from the concatenated payload of its
+    /// directory pages
+    pub fn decode(bytes: &[u8]) -> Result<PageDirector, Error> {
+        bincode::deserialize(bytes).map_err(|_| Error::DecodeDirectoryError)
+    }
 }
 
 impl Default for PageDirector {
@@ -0,0 +1,251 @@
+// double write buffer, a sidecar of the db file with a .dwb
+// extension
+//
+// a page written in place can be torn by a power loss, leaving
+// part old and part new content that fails its checksum. with
+// the buffer enabled every batch of page writes first goes to
+// the sidecar and is synced there, only then are the pages
+// written to their home offsets and the db file synced. a torn
+// home page always has an intact copy in the sidecar, which is
+// copied back when the database is opened
+//
+// the sidecar is split into one region per disk worker. a region
+// starts with the number of pages in the last batch, followed by
+// the batch entries, each the home offset, page id and page LSN
+// of the page and the page as written to the db file (header and
+// content)
+//
+// regions are written independently, so the sidecar can hold
+// several copies for one offset: older ones of the same page, or
+// of a page deleted since whose slot was reused. only the newest
+// copy of the page the offset is mapped to is ever restored
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::wal::Lsn;
+
+use super::{
+    file::StorageBackend,
+    page::{PageHeader, PageID, FRAME_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE},
+};
+
+// extension of the double write sidecar of a db file
+pub const DOUBLE_WRITE_EXTENSION: &str = "dwb";
+
+// pages a region holds, larger batches are split
+pub const DOUBLE_WRITE_BATCH: usize = 32;
+
+const REGION_HEADER_SIZE: u64 = 8;
+const ENTRY_HEADER_SIZE: u64 = 24;
+const ENTRY_SIZE: u64 = ENTRY_HEADER_SIZE + PAGE_SIZE;
+const REGION_SIZE: u64 = REGION_HEADER_SIZE + DOUBLE_WRITE_BATCH as u64 * ENTRY_SIZE;
+
+/// A page as written to the db file, along with where it goes
+#[derive(Debug, Clone, Copy)]
+pub struct DoubleWritePage<'a> {
+    pub page_id: PageID,
+    pub offset: usize,
+    pub page_lsn: Lsn,
+    pub page: &'a [u8; PAGE_SIZE as usize],
+}
+
+#[derive(Debug)]
+pub struct DoubleWriteBuffer {
+    file: Arc<dyn StorageBackend>,
+}
+
+impl DoubleWriteBuffer {
+    /// Creates an empty buffer on `file` with a region for each of
+    /// `regions` workers, dropping whatever it holds
+    pub fn create(file: Arc<dyn StorageBackend>, regions: usize) -> io::Result<DoubleWriteBuffer> {
+        file.set_len(0)?;
+        file.set_len(REGION_SIZE * regions as u64)?;
+        file.sync()?;
+
+        Ok(DoubleWriteBuffer { file })
+    }
+
+    /// Opens the buffer of an existing database stored in `file`,
+    /// grown to hold at least `regions` regions
+    pub fn open(file: Arc<dyn StorageBackend>, regions: usize) -> io::Result<DoubleWriteBuffer> {
+        if file.len()? < REGION_SIZE * regions as u64 {
+            file.set_len(REGION_SIZE * regions as u64)?;
+            file.sync()?;
+        }
+
+        Ok(DoubleWriteBuffer { file })
+    }
+
+    /// Path of the buffer belonging to a db file stored on the file
+    /// system
+    pub fn path(db_file: &str) -> PathBuf {
+        Path::new(db_file).with_extension(DOUBLE_WRITE_EXTENSION)
+    }
+
+    /// Makes a batch of at most `DOUBLE_WRITE_BATCH` pages durable
+    /// in the region, the pages may be written to their home offsets
+    /// once it returns
+    pub fn write_batch(&self, region: usize, pages: &[DoubleWritePage]) -> io::Result<()> {
+        assert!(pages.len() <= DOUBLE_WRITE_BATCH);
+
+        let mut bytes =
+            Vec::with_capacity((REGION_HEADER_SIZE + ENTRY_SIZE * pages.len() as u64) as usize);
+        bytes.extend_from_slice(&(pages.len() as u64).to_le_bytes());
+        for page in pages {
+            bytes.extend_from_slice(&(page.offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&(page.page_id as u64).to_le_bytes());
+            bytes.extend_from_slice(&page.page_lsn.to_le_bytes());
+            bytes.extend_from_slice(page.page);
+        }
+
+        self.file.write_at(&bytes, REGION_SIZE * region as u64)?;
+        self.file.sync()
+    }
+
+    /// Copies the buffered version of every page of the last batches
+    /// whose home copy in `db_file` fails its checksum back in place.
+    /// `mapped` holds the page id every offset in use is mapped to,
+    /// copies of any other page are stale. Returns the offsets of the
+    /// repaired pages
+    pub fn repair(
+        &self,
+        db_file: &dyn StorageBackend,
+        mapped: &HashMap<usize, PageID>,
+    ) -> io::Result<Vec<usize>> {
+        // the newest intact copy of the page mapped at every offset
+        let mut copies: HashMap<usize, (Lsn, [u8; PAGE_SIZE as usize])> = HashMap::new();
+        let regions = self.file.len()? / REGION_SIZE;
+        for region in 0..regions {
+            let mut region_bytes = vec![0; REGION_SIZE as usize];
+            self.file.read_at(&mut region_bytes, region * REGION_SIZE)?;
+
+            let count = u64::from_le_bytes(region_bytes[..8].try_into().unwrap()) as usize;
+            for entry in region_bytes[REGION_HEADER_SIZE as usize..]
+                .chunks_exact(ENTRY_SIZE as usize)
+                .take(count.min(DOUBLE_WRITE_BATCH))
+            {
+                let offset = u64::from_le_bytes(entry[..8].try_into().unwrap()) as usize;
+                let page_id = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+                let page_lsn = u64::from_le_bytes(entry[16..24].try_into().unwrap());
+                let page: [u8; PAGE_SIZE as usize] =
+                    entry[ENTRY_HEADER_SIZE as usize..].try_into().unwrap();
+                if mapped.get(&offset).map(|id| *id as u64) != Some(page_id) || !is_intact(&page) {
+                    continue;
+                }
+                // a page is always written by the same worker, on a tie
+                // the later entry of its region is the newer one
+                match copies.get(&offset) {
+                    Some((newest, _)) if *newest > page_lsn => {}
+                    _ => {
+                        copies.insert(offset, (page_lsn, page));
+                    }
+                }
+            }
+        }
+
+        let mut repaired = vec![];
+        for (offset, (_, page)) in copies {
+            let mut home = [0; PAGE_SIZE as usize];
+            // a page past the end of the file never made it to disk
+            let read = db_file.read_at(&mut home, offset as u64);
+            if read.is_ok() && is_intact(&home) {
+                continue;
+            }
+
+            println!("[DEBUG][DoubleWrite] repairing torn page at offset {offset}");
+            db_file.write_at(&page, offset as u64)?;
+            repaired.push(offset);
+        }
+        if !repaired.is_empty() {
+            db_file.sync()?;
+        }
+
+        repaired.sort();
+        Ok(repaired)
+    }
+}
+
+// whether a page as stored on disk matches its checksum
+fn is_intact(page: &[u8; PAGE_SIZE as usize]) -> bool {
+    let (header, content) = page.split_at(PAGE_HEADER_SIZE as usize);
+    let content: &[u8; FRAME_SIZE as usize] = content.try_into().unwrap();
+    PageHeader::decode(header.try_into().unwrap()).verify(content)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use crate::storage::{
+        file::StorageBackend,
+        memory::MemoryBackend,
+        page::{PageHeader, FRAME_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE},
+    };
+
+    use super::{DoubleWriteBuffer, DoubleWritePage};
+
+    fn page(page_lsn: u64, value: u8) -> [u8; PAGE_SIZE as usize] {
+        let content = [value; FRAME_SIZE as usize];
+        let mut page = [0; PAGE_SIZE as usize];
+        page[..PAGE_HEADER_SIZE as usize]
+            .copy_from_slice(&PageHeader::new(page_lsn, &content).encode());
+        page[PAGE_HEADER_SIZE as usize..].copy_from_slice(&content);
+        page
+    }
+
+    fn entry(
+        page_id: u32,
+        offset: usize,
+        page_lsn: u64,
+        page: &[u8; PAGE_SIZE as usize],
+    ) -> DoubleWritePage<'_> {
+        DoubleWritePage {
+            page_id,
+            offset,
+            page_lsn,
+            page,
+        }
+    }
+
+    #[test]
+    fn test_repair_restores_newest_copy_of_mapped_page() {
+        let (first, second) = (PAGE_SIZE as usize, 2 * PAGE_SIZE as usize);
+
+        let (newest_8, page_7) = (page(6, 0x86), page(3, 0x73));
+        let (page_5, older_8) = (page(9, 0x59), page(4, 0x84));
+
+        let buffer = DoubleWriteBuffer::create(Arc::new(MemoryBackend::new()), 2).unwrap();
+        buffer
+            .write_batch(
+                0,
+                &[entry(8, second, 6, &newest_8), entry(7, first, 3, &page_7)],
+            )
+            .unwrap();
+        // page 5 was deleted since and its slot taken by page 7
+        buffer
+            .write_batch(
+                1,
+                &[entry(5, first, 9, &page_5), entry(8, second, 4, &older_8)],
+            )
+            .unwrap();
+
+        // both home pages are torn
+        let db_file = MemoryBackend::new();
+        db_file
+            .write_at(&[0xee; 3 * PAGE_SIZE as usize], 0)
+            .unwrap();
+        let mapped = HashMap::from([(first, 7), (second, 8)]);
+        assert_eq!(buffer.repair(&db_file, &mapped).unwrap(), [first, second]);
+
+        let mut home = [0; PAGE_SIZE as usize];
+        db_file.read_at(&mut home, first as u64).unwrap();
+        assert_eq!(home, page_7);
+        db_file.read_at(&mut home, second as u64).unwrap();
+        assert_eq!(home, newest_8);
+    }
+}
@@ -0,0 +1,195 @@
+// storage backend wrapping another one and injecting faults
+// into its writes
+//
+// tests register faults for the writes they are interested
+// in, by offset or by their position among the writes that
+// follow, to make a write fail, stall or tear the same way
+// on every run. reads, truncations and syncs are passed on.
+// sidecars, e.g. the log, are wrapped as well and take faults
+// of their own
+
+use std::{collections::HashMap, io, sync::Arc, thread, time::Duration};
+
+use parking_lot::Mutex;
+
+use super::file::StorageBackend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // the write fails with an error of this kind, nothing is written
+    Fail(io::ErrorKind),
+    // the write goes through after sleeping
+    Delay(Duration),
+    // only the first bytes are written and the write reports
+    // success, like a write cut short by a power loss
+    Tear(usize),
+}
+
+/// Writes a fault applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultTarget {
+    Any,
+    // writes starting at the offset
+    Offset(u64),
+    // the nth write after the fault was injected, counting from 0,
+    // the fault only applies once
+    Nth(u64),
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    // faults together with the write count they were injected at
+    faults: Vec<(FaultTarget, Fault, u64)>,
+    writes: u64,
+}
+
+#[derive(Debug)]
+pub struct FaultyBackend {
+    inner: Arc<dyn StorageBackend>,
+    state: Mutex<FaultState>,
+    sidecars: Mutex<HashMap<String, Arc<FaultyBackend>>>,
+}
+
+impl FaultyBackend {
+    pub fn new(inner: Arc<dyn StorageBackend>) -> FaultyBackend {
+        FaultyBackend {
+            inner,
+            state: Mutex::default(),
+            sidecars: Mutex::default(),
+        }
+    }
+
+    /// The sidecar with the given extension, to inject faults into
+    /// its writes. `None` until it was created
+    pub fn faulty_sidecar(&self, extension: &str) -> Option<Arc<FaultyBackend>> {
+        self.sidecars.lock().get(extension).cloned()
+    }
+
+    /// Applies `fault` to the writes matching `target` until the
+    /// faults are cleared. The first matching fault wins
+    pub fn inject(&self, target: FaultTarget, fault: Fault) {
+        let mut state = self.state.lock();
+        let writes = state.writes;
+        state.faults.push((target, fault, writes));
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().faults.clear();
+    }
+
+    /// Number of writes issued to the backend so far, faulty or not
+    pub fn writes(&self) -> u64 {
+        self.state.lock().writes
+    }
+
+    // the fault for the next write, if any
+    fn next_fault(&self, offset: u64) -> Option<Fault> {
+        let mut state = self.state.lock();
+        let write = state.writes;
+        state.writes += 1;
+
+        let idx = state
+            .faults
+            .iter()
+            .position(|(target, _, injected)| match *target {
+                FaultTarget::Any => true,
+                FaultTarget::Offset(target) => target == offset,
+                FaultTarget::Nth(nth) => injected + nth == write,
+            })?;
+        let (target, fault, _) = state.faults[idx];
+        if let FaultTarget::Nth(_) = target {
+            state.faults.remove(idx);
+        }
+        Some(fault)
+    }
+}
+
+impl StorageBackend for FaultyBackend {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.inner.read_at(buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        match self.next_fault(offset) {
+            None => self.inner.write_at(buf, offset),
+            Some(Fault::Fail(kind)) => {
+                println!("[DEBUG][FaultyBackend] failing write at {offset} with {kind}");
+                Err(kind.into())
+            }
+            Some(Fault::Delay(delay)) => {
+                thread::sleep(delay);
+                self.inner.write_at(buf, offset)
+            }
+            Some(Fault::Tear(len)) => {
+                println!("[DEBUG][FaultyBackend] tearing write at {offset} after {len} bytes");
+                self.inner.write_at(&buf[..len.min(buf.len())], offset)
+            }
+        }
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.inner.set_len(len)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.inner.sync()
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        self.inner.len()
+    }
+
+    fn sidecar(
+        &self,
+        extension: &str,
+        create: bool,
+    ) -> io::Result<Option<Arc<dyn StorageBackend>>> {
+        let mut sidecars = self.sidecars.lock();
+        if !sidecars.contains_key(extension) {
+            let Some(inner) = self.inner.sidecar(extension, create)? else {
+                return Ok(None);
+            };
+            sidecars.insert(extension.to_string(), Arc::new(FaultyBackend::new(inner)));
+        }
+
+        Ok(sidecars
+            .get(extension)
+            .map(|sidecar| Arc::clone(sidecar) as Arc<dyn StorageBackend>))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io, sync::Arc};
+
+    use crate::storage::{file::StorageBackend, memory::MemoryBackend};
+
+    use super::{Fault, FaultTarget, FaultyBackend};
+
+    #[test]
+    fn test_faults_apply_to_matching_writes() {
+        let memory = Arc::new(MemoryBackend::new());
+        let backend = FaultyBackend::new(memory.clone());
+        backend.write_at(&[1; 8], 0).unwrap();
+
+        backend.inject(FaultTarget::Nth(1), Fault::Fail(io::ErrorKind::StorageFull));
+        backend.inject(FaultTarget::Offset(16), Fault::Tear(2));
+
+        backend.write_at(&[2; 8], 8).unwrap();
+        let error = backend.write_at(&[3; 8], 0).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        // the nth fault only applies once
+        backend.write_at(&[3; 8], 0).unwrap();
+        backend.write_at(&[4; 8], 16).unwrap();
+        assert_eq!(backend.writes(), 5);
+
+        let mut bytes = [0; 18];
+        memory.read_at(&mut bytes, 0).unwrap();
+        assert_eq!(bytes[..16], [[3; 8], [2; 8]].concat()[..]);
+        assert_eq!(bytes[16..], [4, 4]);
+
+        backend.clear();
+        backend.write_at(&[5; 4], 16).unwrap();
+        assert_eq!(memory.len().unwrap(), 20);
+    }
+}
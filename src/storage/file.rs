@@ -0,0 +1,140 @@
+// storage backends, the byte addressed storage the db file
+// lives on
+//
+// the disk manager and its scheduler only read and write
+// whole pages and the header at fixed offsets, they go
+// through `StorageBackend` instead of a file so the same
+// code runs on an in-memory backend in tests, or on a
+// backend injecting faults. the log and the double write
+// buffer are sidecars of the db file, named by an extension,
+// and live on a backend of the same kind handed out by it
+
+use std::{
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub trait StorageBackend: Debug + Send + Sync {
+    /// Fills `buf` with the bytes at `offset`, reading past the end
+    /// fails with `io::ErrorKind::UnexpectedEof`
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+    /// Writes all of `buf` at `offset`, growing the storage if needed
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()>;
+
+    /// Truncates or zero extends the storage to `len` bytes
+    fn set_len(&self, len: u64) -> io::Result<()>;
+
+    /// Flushes every completed write to stable storage
+    fn sync(&self) -> io::Result<()>;
+
+    fn len(&self) -> io::Result<u64>;
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Backend of the sidecar with the given extension, e.g. the
+    /// log. A missing sidecar is created empty when `create` is set
+    /// and `None` otherwise. Data in it is kept either way
+    fn sidecar(&self, extension: &str, create: bool)
+        -> io::Result<Option<Arc<dyn StorageBackend>>>;
+
+    /// A second handle whose writes are on stable storage once they
+    /// return, used for `Durability::Dsync`. `None` when the backend
+    /// has none, writes are synced one by one instead
+    fn dsync_handle(&self) -> io::Result<Option<Arc<dyn StorageBackend>>> {
+        Ok(None)
+    }
+}
+
+/// Backend storing the db file on the file system
+#[derive(Debug)]
+pub struct FileBackend {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileBackend {
+    /// Creates an empty file, truncating any existing one
+    pub fn create(path: &Path) -> io::Result<FileBackend> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        Ok(FileBackend {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Opens a file without truncating it, a missing file is created
+    pub fn open(path: &Path) -> io::Result<FileBackend> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        Ok(FileBackend {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.file.read_exact_at(buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        self.file.write_all_at(buf, offset)
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn sidecar(
+        &self,
+        extension: &str,
+        create: bool,
+    ) -> io::Result<Option<Arc<dyn StorageBackend>>> {
+        let path = self.path.with_extension(extension);
+        if !create && !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(Arc::new(FileBackend::open(&path)?)))
+    }
+
+    fn dsync_handle(&self) -> io::Result<Option<Arc<dyn StorageBackend>>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_DSYNC)
+            .open(&self.path)?;
+
+        Ok(Some(Arc::new(FileBackend {
+            file,
+            path: self.path.clone(),
+        })))
+    }
+}
@@ -0,0 +1,109 @@
+// this file defines the header page (superblock) stored
+// at offset 0 of every db file
+//
+// the header identifies the file as a forklift database,
+// records the parameters it was created with and points
+// to the pages holding the serialized page directory
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::page::PAGE_SIZE;
+
+pub const MAGIC: [u8; 8] = *b"FORKLIFT";
+pub const FORMAT_VERSION: u32 = 6;
+
+// the whole first page is reserved for the header, data
+// pages start right after it
+pub const HEADER_SIZE: u64 = PAGE_SIZE;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileHeader {
+    pub magic: [u8; 8],
+    pub version: u32,
+    // size of the blocks pages are stored in
+    pub page_size: u64,
+    // max_frames the database was created with
+    pub max_frames: u64,
+    // offset of the first directory page, 0 when the
+    // directory has never been persisted
+    pub directory_offset: u64,
+    // the persisted directory holds every directory change
+    // logged below this LSN, recovery skips replaying them
+    pub directory_lsn: u64,
+    // master record, LSN of the begin record of the last
+    // complete checkpoint or 0 when there is none
+    pub checkpoint_lsn: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    TruncatedHeader,
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    PageSizeMismatch(u64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::TruncatedHeader => write!(f, "file is too short to hold a database header"),
+            Self::InvalidMagic => write!(f, "file is not a forklift database, bad magic"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "database format version {version} is not supported, expected {FORMAT_VERSION}"
+            ),
+            Self::PageSizeMismatch(page_size) => write!(
+                f,
+                "database was created with page size {page_size}, expected {PAGE_SIZE}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl FileHeader {
+    pub fn new(max_frames: usize) -> FileHeader {
+        FileHeader {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            page_size: PAGE_SIZE,
+            max_frames: max_frames as u64,
+            directory_offset: 0,
+            directory_lsn: 0,
+            checkpoint_lsn: 0,
+        }
+    }
+
+    /// Serializes the header into a zero padded page
+    pub fn encode(&self) -> Box<[u8; HEADER_SIZE as usize]> {
+        let mut content = Box::new([0; HEADER_SIZE as usize]);
+        bincode::serialize_into(&mut content[..], self)
+            .expect("header always fits in a single page");
+        content
+    }
+
+    /// Decodes and validates a header read from offset 0 of
+    /// a db file
+    pub fn decode(bytes: &[u8]) -> Result<FileHeader, Error> {
+        if bytes.len() < HEADER_SIZE as usize {
+            return Err(Error::TruncatedHeader);
+        }
+        if bytes[..MAGIC.len()] != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+
+        let header: FileHeader = bincode::deserialize(bytes).map_err(|_| Error::TruncatedHeader)?;
+
+        if header.version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(header.version));
+        }
+        if header.page_size != PAGE_SIZE {
+            return Err(Error::PageSizeMismatch(header.page_size));
+        }
+
+        Ok(header)
+    }
+}
@@ -0,0 +1,79 @@
+// storage backend keeping the db file in memory
+//
+// meant for tests that do not need to survive the process.
+// the bytes live as long as the backend, so a database can
+// be closed and reopened on the same backend. sidecars are
+// memory backends held by the backend of the db file
+
+use std::{collections::HashMap, io, sync::Arc};
+
+use parking_lot::{Mutex, RwLock};
+
+use super::file::StorageBackend;
+
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    bytes: RwLock<Vec<u8>>,
+    sidecars: Mutex<HashMap<String, Arc<MemoryBackend>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+
+    /// Copy of the stored bytes
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.bytes.read().clone()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let bytes = self.bytes.read();
+        let start = offset as usize;
+        let Some(source) = bytes.get(start..start + buf.len()) else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        buf.copy_from_slice(source);
+        Ok(())
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let mut bytes = self.bytes.write();
+        let start = offset as usize;
+        if bytes.len() < start + buf.len() {
+            bytes.resize(start + buf.len(), 0);
+        }
+        bytes[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.bytes.write().resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.bytes.read().len() as u64)
+    }
+
+    fn sidecar(
+        &self,
+        extension: &str,
+        create: bool,
+    ) -> io::Result<Option<Arc<dyn StorageBackend>>> {
+        let mut sidecars = self.sidecars.lock();
+        if create {
+            sidecars.entry(extension.to_string()).or_default();
+        }
+
+        Ok(sidecars
+            .get(extension)
+            .map(|sidecar| Arc::clone(sidecar) as Arc<dyn StorageBackend>))
+    }
+}
@@ -1,3 +1,8 @@
+pub mod checksum;
 pub mod directory;
+pub mod doublewrite;
+pub mod fault;
 pub mod file;
+pub mod header;
+pub mod memory;
 pub mod page;
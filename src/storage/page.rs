@@ -5,10 +5,75 @@
 // each individual page is supposed to be self
 // contained
 
-use std::{fmt::Display, io, sync::Arc};
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Cursor, Read, Seek, SeekFrom, Write},
+};
+
+use crate::{
+    buffer::arena::FrameBuf,
+    wal::{Lsn, INVALID_LSN},
+};
+
+use super::checksum::{crc32c, crc32c_append};
 
 pub type PageID = u32;
-pub const FRAME_SIZE: u64 = 4096; // 4KB frame size
+
+// pages are stored in 4KB blocks aligned to their size in the
+// db file. a block starts with a small header holding the page
+// LSN and checksum, the rest is the content of the frame
+//
+// the header moved inside the block with format version 6, so a
+// frame holds FRAME_SIZE = 4080 bytes instead of a whole 4096.
+// files of older versions are rejected when opened. offsets and
+// sizes in the db file are in PAGE_SIZE blocks, FRAME_SIZE only
+// bounds what a page can hold
+pub const PAGE_SIZE: u64 = 4096;
+pub const PAGE_HEADER_SIZE: u64 = 16;
+pub const FRAME_SIZE: u64 = PAGE_SIZE - PAGE_HEADER_SIZE;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageHeader {
+    // LSN of the last logged change applied to the page
+    pub page_lsn: Lsn,
+    // CRC32C over the page LSN and the content
+    pub checksum: u32,
+}
+
+impl PageHeader {
+    /// Header of a page about to be written with `content`
+    pub fn new(page_lsn: Lsn, content: &[u8; FRAME_SIZE as usize]) -> PageHeader {
+        PageHeader {
+            page_lsn,
+            checksum: PageHeader::compute_checksum(page_lsn, content),
+        }
+    }
+
+    fn compute_checksum(page_lsn: Lsn, content: &[u8; FRAME_SIZE as usize]) -> u32 {
+        crc32c_append(crc32c(&page_lsn.to_le_bytes()), content)
+    }
+
+    /// Whether `content` is what the page was written with. A slot
+    /// that was never written holds only zeros and is valid too
+    pub fn verify(&self, content: &[u8; FRAME_SIZE as usize]) -> bool {
+        self.checksum == PageHeader::compute_checksum(self.page_lsn, content)
+            || (*self == PageHeader::default() && content.iter().all(|byte| *byte == 0))
+    }
+
+    pub fn encode(&self) -> [u8; PAGE_HEADER_SIZE as usize] {
+        let mut bytes = [0; PAGE_HEADER_SIZE as usize];
+        bytes[..8].copy_from_slice(&self.page_lsn.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; PAGE_HEADER_SIZE as usize]) -> PageHeader {
+        PageHeader {
+            page_lsn: Lsn::from_le_bytes(bytes[..8].try_into().unwrap()),
+            checksum: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        }
+    }
+}
 
 // pages goes synonymously with frames, frames being
 // 4KB block of memory that will be pointed to in the
@@ -18,55 +83,134 @@ pub const FRAME_SIZE: u64 = 4096; // 4KB frame size
 pub struct Frame {
     pub page_id: PageID,
     pub dirty: bool,
+    // bumped whenever the frame is marked dirty, a write back
+    // only marks the frame clean when it was not modified after
+    // the write was scheduled
+    pub modifications: u64,
+    pub page_lsn: Lsn,
     pub offset: usize,
     pub cursor: usize,
-    pub content: Box<[u8; FRAME_SIZE as usize]>,
+    pub content: FrameBuf,
 }
 
 impl Display for Frame {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "page_id_t {} dirty {} offset {}",
-            self.page_id, self.dirty, self.offset
+            "page_id_t {} dirty {} lsn {} offset {}",
+            self.page_id, self.dirty, self.page_lsn, self.offset
         )
     }
 }
 
 impl Frame {
-    pub fn new(page_id: PageID, offset: usize, content: Box<[u8; FRAME_SIZE as usize]>) -> Frame {
+    pub fn new(page_id: PageID, offset: usize, content: FrameBuf) -> Frame {
         Frame {
             page_id,
             offset,
             content,
             cursor: 0,
             dirty: false,
+            modifications: 0,
+            page_lsn: INVALID_LSN,
         }
     }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.modifications += 1;
+    }
 }
 
-impl io::Read for Frame {
-    // not completely implemented
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let mut buf_len = buf.len();
-        let read_available = FRAME_SIZE as usize - self.cursor;
-        if buf_len > read_available {
-            buf_len = read_available;
-        }
-        buf.copy_from_slice(&self.content.as_slice()[self.cursor..self.cursor + buf_len]);
-        Ok(buf_len)
+// the frame content is read and written from `cursor` on, like a
+// file of FRAME_SIZE bytes. reads past the end return nothing and
+// writes stop at the end, `write_all` fails there with WriteZero
+impl Read for Frame {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut cursor = Cursor::new(&self.content[..]);
+        cursor.set_position(self.cursor as u64);
+        let read = cursor.read(buf)?;
+        self.cursor = cursor.position() as usize;
+        Ok(read)
     }
 }
 
-impl io::Write for Frame {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.dirty = true;
+impl BufRead for Frame {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let start = self.cursor.min(self.content.len());
+        Ok(&self.content[start..])
+    }
 
-        Ok(0)
+    fn consume(&mut self, amt: usize) {
+        self.cursor += amt;
     }
+}
 
+impl Seek for Frame {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut cursor = Cursor::new(&self.content[..]);
+        cursor.set_position(self.cursor as u64);
+        let position = cursor.seek(pos)?;
+        self.cursor = position as usize;
+        Ok(position)
+    }
+}
+
+impl Write for Frame {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut cursor = Cursor::new(&mut self.content[..]);
+        cursor.set_position(self.cursor as u64);
+        let written = cursor.write(buf)?;
+        self.cursor = cursor.position() as usize;
+        if written > 0 {
+            self.mark_dirty();
+        }
+        Ok(written)
+    }
+
+    // a frame does not know the pool holding it, the page is
+    // persisted by flushing the `WritePageGuard` it was changed through
     fn flush(&mut self) -> io::Result<()> {
-        // somehow request the diskmanager to flush the page
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+    use crate::buffer::arena::FrameBuf;
+
+    use super::{Frame, FRAME_SIZE};
+
+    #[test]
+    fn test_frame_cursor() {
+        let mut frame = Frame::new(1, 0, FrameBuf::default());
+        frame.write_all(b"first line\nsecond").unwrap();
+        assert!(frame.dirty);
+
+        frame.rewind().unwrap();
+        let mut line = String::new();
+        frame.read_line(&mut line).unwrap();
+        assert_eq!(line, "first line\n");
+        let mut word = [0; 6];
+        frame.read_exact(&mut word).unwrap();
+        assert_eq!(&word, b"second");
+
+        // reads past the end return what is left instead of panicking
+        let end = frame.seek(SeekFrom::End(-4)).unwrap();
+        assert_eq!(end, FRAME_SIZE - 4);
+        let mut buf = [1; 16];
+        assert_eq!(frame.read(&mut buf).unwrap(), 4);
+        assert_eq!(frame.read(&mut buf).unwrap(), 0);
+        assert!(frame
+            .seek(SeekFrom::Current(-(FRAME_SIZE as i64) - 1))
+            .is_err());
+
+        // writes stop at the end of the page
+        frame.seek(SeekFrom::Start(FRAME_SIZE - 2)).unwrap();
+        let error = frame.write_all(b"abc").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+        assert_eq!(&frame.content[FRAME_SIZE as usize - 2..], b"ab");
+    }
+}
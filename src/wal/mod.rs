@@ -0,0 +1,542 @@
+// write ahead log
+//
+// every logged change to a page is appended to the log
+// before the page itself may reach the db file. a record
+// is identified by its log sequence number (LSN) and each
+// frame remembers the LSN of the last record applied to
+// it. the buffer pool makes the log durable up to that LSN
+// before the frame is written back (WAL before data)
+//
+// changes are grouped into transactions, the records of a
+// transaction are chained through their `prev_lsn` so they
+// can be undone newest first
+//
+// records are buffered in memory and only written out when
+// a flush asks for them or the buffer fills up. the log is
+// a sidecar of the db file with a .log extension and starts
+// with a header holding the LSN of its first record, so
+// LSNs keep growing when the log is reset
+
+pub mod record;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use record::{LogBody, LogRecord, RECORD_HEADER_SIZE};
+
+use crate::{
+    error::Result,
+    storage::{file::StorageBackend, page::PageID},
+};
+
+pub type Lsn = u64;
+pub type TxnId = u64;
+
+// begin LSN, dirty page table and running transactions of a
+// checkpoint in progress
+pub type Checkpoint = (Lsn, Vec<(PageID, Lsn)>, Vec<(TxnId, Lsn)>);
+
+// LSN of a page no logged change was applied to yet
+pub const INVALID_LSN: Lsn = 0;
+// transaction id of records logged outside of a transaction
+pub const INVALID_TXN: TxnId = 0;
+
+// extension of the log sidecar of a db file
+pub const LOG_EXTENSION: &str = "log";
+
+const LOG_MAGIC: [u8; 8] = *b"FORKLOG1";
+// magic followed by the LSN of the first record
+const LOG_HEADER_SIZE: u64 = 16;
+// buffered records are written out, without a sync, past this size
+const LOG_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    InvalidMagic,
+    UnknownTransaction(TxnId),
+    // the log can only be reset once every transaction ended
+    ActiveTransactions,
+    RecordNotFound(Lsn),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::InvalidMagic => write!(f, "file is not a forklift log, bad magic"),
+            Self::UnknownTransaction(txn_id) => write!(f, "transaction {txn_id} is not active"),
+            Self::ActiveTransactions => write!(f, "log can not be reset with active transactions"),
+            Self::RecordNotFound(lsn) => write!(f, "log holds no record at LSN {lsn}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug)]
+struct LogState {
+    file: Arc<dyn StorageBackend>,
+    // LSN of the first record in the file
+    base_lsn: Lsn,
+    // records appended but not written to the file yet
+    buffer: Vec<u8>,
+    // LSN the next record gets, the end of the log
+    next_lsn: Lsn,
+    // last LSN of every running transaction
+    active: HashMap<TxnId, Lsn>,
+    next_txn: TxnId,
+    // dirty page table, (recLSN, last LSN) of every page with
+    // logged changes that may not have reached the db file yet
+    dirty_pages: HashMap<PageID, (Lsn, Lsn)>,
+}
+
+impl LogState {
+    fn position(&self, lsn: Lsn) -> u64 {
+        lsn - self.base_lsn + LOG_HEADER_SIZE
+    }
+
+    fn write_buffer(&mut self) -> std::io::Result<()> {
+        let position = self.position(self.next_lsn) - self.buffer.len() as u64;
+        self.file.write_at(&self.buffer, position)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn append(&mut self, txn_id: TxnId, body: LogBody) -> Result<Lsn> {
+        let prev_lsn = match (txn_id, &body) {
+            (INVALID_TXN, _) | (_, LogBody::Begin) => INVALID_LSN,
+            _ => *self
+                .active
+                .get(&txn_id)
+                .ok_or(Error::UnknownTransaction(txn_id))?,
+        };
+
+        let lsn = self.next_lsn;
+        let ends = matches!(body, LogBody::Commit | LogBody::End);
+        if let LogBody::PageWrite { page_id, .. } | LogBody::Compensation { page_id, .. } = body {
+            self.dirty_pages.entry(page_id).or_insert((lsn, lsn)).1 = lsn;
+        }
+        let bytes = LogRecord {
+            lsn,
+            txn_id,
+            prev_lsn,
+            body,
+        }
+        .encode();
+        self.buffer.extend_from_slice(&bytes);
+        self.next_lsn += bytes.len() as u64;
+
+        if txn_id != INVALID_TXN {
+            if ends {
+                self.active.remove(&txn_id);
+            } else {
+                self.active.insert(txn_id, lsn);
+            }
+        }
+        if self.buffer.len() > LOG_BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+
+        Ok(lsn)
+    }
+}
+
+#[derive(Debug)]
+pub struct LogManager {
+    state: Mutex<LogState>,
+    // every record below this LSN is on stable storage
+    durable_lsn: AtomicU64,
+}
+
+impl LogManager {
+    /// Creates an empty log on `file`, truncating whatever it holds
+    pub fn create(file: Arc<dyn StorageBackend>) -> Result<LogManager> {
+        file.set_len(0)?;
+        // the header size keeps the first LSN clear of INVALID_LSN
+        write_header(&*file, LOG_HEADER_SIZE)?;
+
+        Ok(LogManager::with_state(
+            file,
+            LOG_HEADER_SIZE,
+            LOG_HEADER_SIZE,
+            1,
+        ))
+    }
+
+    /// Opens the log of an existing database stored in `file`, an
+    /// empty log is created. A record cut short by a crash at the
+    /// end of the log is dropped
+    pub fn open(file: Arc<dyn StorageBackend>) -> Result<LogManager> {
+        if file.is_empty()? {
+            return LogManager::create(file);
+        }
+
+        let mut header = [0; LOG_HEADER_SIZE as usize];
+        file.read_at(&mut header, 0)
+            .map_err(|_| Error::InvalidMagic)?;
+        if header[..LOG_MAGIC.len()] != LOG_MAGIC {
+            return Err(Error::InvalidMagic.into());
+        }
+        let base_lsn = Lsn::from_le_bytes(header[LOG_MAGIC.len()..].try_into().unwrap());
+
+        let (records, size) = scan(&*file, base_lsn, base_lsn)?;
+        let end = LOG_HEADER_SIZE + size as u64;
+        if end < file.len()? {
+            println!("[DEBUG][WAL] dropping torn log tail at {end}");
+            file.set_len(end)?;
+            file.sync()?;
+        }
+
+        let next_txn = records
+            .iter()
+            .map(|record| record.txn_id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        Ok(LogManager::with_state(
+            file,
+            base_lsn,
+            base_lsn + size as u64,
+            next_txn,
+        ))
+    }
+
+    fn with_state(
+        file: Arc<dyn StorageBackend>,
+        base_lsn: Lsn,
+        next_lsn: Lsn,
+        next_txn: TxnId,
+    ) -> LogManager {
+        LogManager {
+            state: Mutex::new(LogState {
+                file,
+                base_lsn,
+                buffer: vec![],
+                next_lsn,
+                active: HashMap::new(),
+                next_txn,
+                dirty_pages: HashMap::new(),
+            }),
+            durable_lsn: AtomicU64::new(next_lsn),
+        }
+    }
+
+    /// Path of the log belonging to a db file stored on the file
+    /// system
+    pub fn log_path(db_file: &str) -> PathBuf {
+        Path::new(db_file).with_extension(LOG_EXTENSION)
+    }
+
+    /// Appends a record and returns its LSN, the record is not
+    /// durable until the log is flushed past it. Records of a
+    /// transaction are only accepted while it is active
+    pub fn append(&self, txn_id: TxnId, body: LogBody) -> Result<Lsn> {
+        self.state.lock().unwrap().append(txn_id, body)
+    }
+
+    /// Starts a transaction
+    pub fn begin(&self) -> Result<TxnId> {
+        let mut state = self.state.lock().unwrap();
+        let txn_id = state.next_txn;
+        state.next_txn += 1;
+        state.append(txn_id, LogBody::Begin)?;
+
+        Ok(txn_id)
+    }
+
+    /// Logs the commit of a transaction and waits until it is durable
+    pub fn commit(&self, txn_id: TxnId) -> Result<Lsn> {
+        let lsn = self.append(txn_id, LogBody::Commit)?;
+        self.flush(lsn)?;
+
+        Ok(lsn)
+    }
+
+    /// Marks a transaction found in the log as active again, so its
+    /// rollback can be logged after a restart
+    pub fn resume(&self, txn_id: TxnId, last_lsn: Lsn) {
+        let mut state = self.state.lock().unwrap();
+        state.active.insert(txn_id, last_lsn);
+        state.next_txn = state.next_txn.max(txn_id + 1);
+    }
+
+    /// Running transactions with the LSN of their last record
+    pub fn active_transactions(&self) -> Vec<(TxnId, Lsn)> {
+        let state = self.state.lock().unwrap();
+        state.active.iter().map(|(txn, lsn)| (*txn, *lsn)).collect()
+    }
+
+    /// Makes the log durable up to and including the record at `lsn`
+    pub fn flush(&self, lsn: Lsn) -> Result<()> {
+        if self.is_durable(lsn) {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        // a concurrent flush may have covered it already
+        if self.is_durable(lsn) {
+            return Ok(());
+        }
+
+        state.write_buffer()?;
+        state.file.sync()?;
+        self.durable_lsn.store(state.next_lsn, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Flushes every record appended so far
+    pub fn flush_all(&self) -> Result<()> {
+        self.flush(self.next_lsn() - 1)
+    }
+
+    /// Whether the record at `lsn` is on stable storage
+    pub fn is_durable(&self, lsn: Lsn) -> bool {
+        lsn < self.durable_lsn.load(Ordering::SeqCst)
+    }
+
+    /// LSN the next appended record gets
+    pub fn next_lsn(&self) -> Lsn {
+        self.state.lock().unwrap().next_lsn
+    }
+
+    /// Whether the log holds no records
+    pub fn is_empty(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.next_lsn == state.base_lsn
+    }
+
+    /// LSN of the first record in the log
+    pub fn base_lsn(&self) -> Lsn {
+        self.state.lock().unwrap().base_lsn
+    }
+
+    /// Called once a write back of the page carrying every change
+    /// up to `page_lsn` completed. Changes logged after the write
+    /// was scheduled keep the page in the dirty page table
+    pub fn page_written(&self, page_id: PageID, page_lsn: Lsn) {
+        let mut state = self.state.lock().unwrap();
+        let Some((rec_lsn, last_lsn)) = state.dirty_pages.get_mut(&page_id) else {
+            return;
+        };
+
+        if *last_lsn <= page_lsn {
+            state.dirty_pages.remove(&page_id);
+        } else {
+            // the first change missing from the file is past `page_lsn`
+            *rec_lsn = (*rec_lsn).max(page_lsn + 1);
+        }
+    }
+
+    /// Drops a deleted page from the dirty page table
+    pub fn page_deleted(&self, page_id: PageID) {
+        self.state.lock().unwrap().dirty_pages.remove(&page_id);
+    }
+
+    /// Appends the begin record of a checkpoint, returns its LSN with
+    /// the dirty page table and the running transactions taken at the
+    /// same point of the log
+    pub fn begin_checkpoint(&self) -> Result<Checkpoint> {
+        let mut state = self.state.lock().unwrap();
+        let lsn = state.append(INVALID_TXN, LogBody::BeginCheckpoint)?;
+
+        let mut dirty_pages: Vec<(PageID, Lsn)> = state
+            .dirty_pages
+            .iter()
+            .map(|(page_id, (rec_lsn, _))| (*page_id, *rec_lsn))
+            .collect();
+        dirty_pages.sort();
+        let mut transactions: Vec<(TxnId, Lsn)> =
+            state.active.iter().map(|(txn, lsn)| (*txn, *lsn)).collect();
+        transactions.sort();
+
+        Ok((lsn, dirty_pages, transactions))
+    }
+
+    /// Appends the end record of a checkpoint started with
+    /// `LogManager::begin_checkpoint` and waits until it is durable
+    pub fn end_checkpoint(&self, (_, dirty_pages, transactions): Checkpoint) -> Result<Lsn> {
+        let lsn = self.append(
+            INVALID_TXN,
+            LogBody::EndCheckpoint {
+                dirty_pages,
+                transactions,
+            },
+        )?;
+        self.flush(lsn)?;
+
+        Ok(lsn)
+    }
+
+    /// Reads the record at `lsn`, buffered records are written
+    /// out first
+    pub fn read_record(&self, lsn: Lsn) -> Result<LogRecord> {
+        let mut state = self.state.lock().unwrap();
+        if lsn < state.base_lsn || lsn >= state.next_lsn {
+            return Err(Error::RecordNotFound(lsn).into());
+        }
+        state.write_buffer()?;
+
+        let position = state.position(lsn);
+        let mut len = [0; RECORD_HEADER_SIZE];
+        state.file.read_at(&mut len, position)?;
+        let mut bytes = vec![0; RECORD_HEADER_SIZE + u32::from_le_bytes(len) as usize];
+        state.file.read_at(&mut bytes, position)?;
+
+        match LogRecord::decode(&bytes) {
+            Some((record, _)) if record.lsn == lsn => Ok(record),
+            _ => Err(Error::RecordNotFound(lsn).into()),
+        }
+    }
+
+    /// Every record in the log, oldest first. Records still
+    /// buffered are written out first
+    pub fn records(&self) -> Result<Vec<LogRecord>> {
+        let mut state = self.state.lock().unwrap();
+        state.write_buffer()?;
+
+        Ok(scan(&*state.file, state.base_lsn, state.base_lsn)?.0)
+    }
+
+    /// Every record from the one at `lsn` to the end of the log
+    pub fn records_from(&self, lsn: Lsn) -> Result<Vec<LogRecord>> {
+        let mut state = self.state.lock().unwrap();
+        if lsn < state.base_lsn || lsn > state.next_lsn {
+            return Err(Error::RecordNotFound(lsn).into());
+        }
+        state.write_buffer()?;
+
+        Ok(scan(&*state.file, state.base_lsn, lsn)?.0)
+    }
+
+    /// Drops every record once the pages they describe are all on
+    /// disk. LSNs handed out afterwards keep growing from where the
+    /// log ended
+    pub fn reset(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.active.is_empty() {
+            return Err(Error::ActiveTransactions.into());
+        }
+
+        // the new base goes first, records left behind by a crash
+        // before the truncation no longer match their LSNs
+        state.buffer.clear();
+        state.dirty_pages.clear();
+        write_header(&*state.file, state.next_lsn)?;
+        state.file.set_len(LOG_HEADER_SIZE)?;
+        state.file.sync()?;
+        state.base_lsn = state.next_lsn;
+        self.durable_lsn.store(state.next_lsn, Ordering::SeqCst);
+
+        Ok(())
+    }
+}
+
+fn write_header(file: &dyn StorageBackend, base_lsn: Lsn) -> std::io::Result<()> {
+    let mut header = [0; LOG_HEADER_SIZE as usize];
+    header[..LOG_MAGIC.len()].copy_from_slice(&LOG_MAGIC);
+    header[LOG_MAGIC.len()..].copy_from_slice(&base_lsn.to_le_bytes());
+
+    file.write_at(&header, 0)?;
+    file.sync()
+}
+
+// reads the complete records of a log file starting at `base_lsn`
+// from the one at `from` on, returns them with the number of bytes
+// they take up
+fn scan(file: &dyn StorageBackend, base_lsn: Lsn, from: Lsn) -> Result<(Vec<LogRecord>, usize)> {
+    let start = LOG_HEADER_SIZE + from - base_lsn;
+    let len = file.len()?.saturating_sub(start) as usize;
+    let mut bytes = vec![0; len];
+    file.read_at(&mut bytes, start)?;
+
+    let mut records = vec![];
+    let mut position = 0;
+    while let Some((record, size)) = LogRecord::decode(&bytes[position..]) {
+        if record.lsn != from + position as u64 {
+            break;
+        }
+        records.push(record);
+        position += size;
+    }
+
+    Ok((records, position))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::storage::{file::StorageBackend, memory::MemoryBackend};
+
+    use super::{record::LogBody, LogManager, INVALID_LSN, INVALID_TXN};
+
+    fn write(page_id: u32, byte: u8) -> LogBody {
+        LogBody::PageWrite {
+            page_id,
+            offset: 0,
+            before: vec![0],
+            after: vec![byte],
+        }
+    }
+
+    #[test]
+    fn test_log_append_flush_reopen() {
+        let file = Arc::new(MemoryBackend::new());
+        let log = LogManager::create(file.clone()).unwrap();
+        let first = log.append(INVALID_TXN, write(1, 1)).unwrap();
+        let second = log.append(INVALID_TXN, write(2, 2)).unwrap();
+        assert!(INVALID_LSN < first && first < second);
+        assert!(log.is_durable(INVALID_LSN));
+        assert!(!log.is_durable(first));
+
+        log.flush(first).unwrap();
+        assert!(log.is_durable(second));
+        drop(log);
+
+        // a record cut short at the end of the log is dropped
+        file.set_len(file.len().unwrap() - 1).unwrap();
+
+        let log = LogManager::open(file).unwrap();
+        let records = log.records().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].lsn, first);
+        assert_eq!(records[0].body, write(1, 1));
+        assert_eq!(log.next_lsn(), second);
+    }
+
+    #[test]
+    fn test_log_transactions_and_reset() {
+        let file = Arc::new(MemoryBackend::new());
+        let log = LogManager::create(file.clone()).unwrap();
+        let txn = log.begin().unwrap();
+        let begin = log.active_transactions()[0].1;
+        let update = log.append(txn, write(1, 1)).unwrap();
+        assert!(log.append(txn + 1, write(1, 2)).is_err());
+
+        // records of a transaction are chained newest to oldest
+        assert_eq!(log.read_record(update).unwrap().prev_lsn, begin);
+        assert_eq!(log.active_transactions(), vec![(txn, update)]);
+        assert!(log.reset().is_err());
+
+        let commit = log.commit(txn).unwrap();
+        assert!(log.is_durable(commit));
+        assert!(log.active_transactions().is_empty());
+
+        log.reset().unwrap();
+        assert!(log.records().unwrap().is_empty());
+        drop(log);
+
+        // LSNs keep growing after a reset
+        let log = LogManager::open(file).unwrap();
+        assert!(log.next_lsn() > commit);
+        log.begin().unwrap();
+        assert!(log.read_record(log.active_transactions()[0].1).is_ok());
+    }
+}
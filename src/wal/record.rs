@@ -0,0 +1,89 @@
+// log records and their encoding in the log file
+//
+// a record is stored as a 4 byte little endian length
+// followed by the bincode encoded record. the length
+// lets a reader skip to the next record and detect a
+// record cut short by a crash at the end of the log
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::page::PageID;
+
+use super::{Lsn, TxnId};
+
+pub const RECORD_HEADER_SIZE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogBody {
+    Begin,
+    Commit,
+    // the transaction is being rolled back
+    Abort,
+    // last record of a rolled back transaction
+    End,
+    // bytes `offset..offset + after.len()` of the page were
+    // replaced, `before` holds what was there before
+    PageWrite {
+        page_id: PageID,
+        offset: usize,
+        before: Vec<u8>,
+        after: Vec<u8>,
+    },
+    // compensation log record (CLR) written when a page write is
+    // undone, it is only ever redone. `undo_next_lsn` is the next
+    // record of the transaction left to undo
+    Compensation {
+        page_id: PageID,
+        offset: usize,
+        after: Vec<u8>,
+        undo_next_lsn: Lsn,
+    },
+    // page directory changes, logged outside of transactions
+    NewPage {
+        page_id: PageID,
+        offset: usize,
+    },
+    DeletePage {
+        page_id: PageID,
+    },
+    // fuzzy checkpoint, the end record holds the dirty page table
+    // (page id, recLSN) and the running transactions (id, last LSN)
+    // as they were when the begin record was written
+    BeginCheckpoint,
+    EndCheckpoint {
+        dirty_pages: Vec<(PageID, Lsn)>,
+        transactions: Vec<(TxnId, Lsn)>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub lsn: Lsn,
+    // `INVALID_TXN` for records that are not part of a transaction
+    pub txn_id: TxnId,
+    // previous record of the same transaction
+    pub prev_lsn: Lsn,
+    pub body: LogBody,
+}
+
+impl LogRecord {
+    /// Encodes the record together with its length prefix
+    pub fn encode(&self) -> Vec<u8> {
+        let payload = bincode::serialize(self).expect("log records always serialize");
+
+        let mut bytes = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Decodes the record at the start of `bytes`, returns it with
+    /// its encoded size or `None` if `bytes` holds no complete record
+    pub fn decode(bytes: &[u8]) -> Option<(LogRecord, usize)> {
+        let len = u32::from_le_bytes(bytes.get(..RECORD_HEADER_SIZE)?.try_into().unwrap()) as usize;
+        let payload = bytes.get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + len)?;
+        let record = bincode::deserialize(payload).ok()?;
+
+        Some((record, RECORD_HEADER_SIZE + len))
+    }
+}